@@ -0,0 +1,419 @@
+//! Checks of the rows of a collection (unreadable rows, malformed fields, duplicates, results
+//! from the future, channels written in several casings) and their fix.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use chrono_tz::Tz;
+use itertools::Itertools;
+
+use crate::FirstResult;
+use crate::database::CsvRecord;
+
+/// How far in the future a result can be before it is reported, to allow for clock drift.
+const FUTURE_TOLERANCE: TimeDelta = TimeDelta::days(1);
+
+/// A row of a collection as written, whether or not it is a valid result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    /// Fields of the row, in the layout of a result.
+    pub fields: Vec<String>,
+}
+
+/// Rows are read in the layout of a result, migrated from older versions.
+impl CsvRecord for Row {
+    const HEADERS: &'static [&'static str] = FirstResult::HEADERS;
+    const VERSION: u32 = FirstResult::VERSION;
+
+    fn from_fields(fields: &[String]) -> Result<Self, String> {
+        Ok(Self {
+            fields: fields.to_vec(),
+        })
+    }
+
+    fn to_fields(&self) -> Vec<String> {
+        self.fields.clone()
+    }
+
+    fn migrate(version: u32, fields: Vec<String>) -> Result<Vec<String>, String> {
+        FirstResult::migrate(version, fields)
+    }
+}
+
+/// Something wrong with the rows of a collection.
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    /// The row cannot be decoded, as invalid CSV or UTF-8, and cannot be fixed automatically.
+    Unreadable {
+        /// Line of the row in the collection file, starting at 1, if known.
+        line: Option<u64>,
+        /// What the row failed on.
+        message: String,
+    },
+    /// The row does not have the 4 fields of a result.
+    Fields(usize),
+    /// The datetime of the row cannot be read.
+    Timestamp(String),
+    /// The timezone of the row is not an IANA name.
+    Timezone(String),
+    /// The row repeats an earlier row.
+    Duplicate {
+        /// Row repeated, starting at 1.
+        of: usize,
+    },
+    /// The row was played after now, beyond the tolerance for clock drift.
+    Future(DateTime<Utc>),
+    /// A channel is written with different casings, the most common one being canonical.
+    ChannelCase {
+        /// The other casing.
+        channel: String,
+        /// The most common casing.
+        canonical: String,
+        /// Number of rows written with the other casing.
+        rows: usize,
+    },
+}
+
+impl Problem {
+    /// Short name of the problem, stable for the JSON and CSV reports.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Problem::Unreadable { .. } => "unreadable",
+            Problem::Fields(_) => "fields",
+            Problem::Timestamp(_) => "timestamp",
+            Problem::Timezone(_) => "timezone",
+            Problem::Duplicate { .. } => "duplicate",
+            Problem::Future(_) => "future",
+            Problem::ChannelCase { .. } => "channel_case",
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Unreadable {
+                line: Some(line),
+                message,
+            } => write!(f, "line {line} cannot be read: {message}"),
+            Problem::Unreadable { message, .. } => write!(f, "row cannot be read: {message}"),
+            Problem::Fields(count) => write!(f, "expected 4 fields, found {count}"),
+            Problem::Timestamp(value) => write!(f, "invalid timestamp {value}"),
+            Problem::Timezone(value) => write!(f, "unknown timezone {value}"),
+            Problem::Duplicate { of } => write!(f, "duplicate of row {of}"),
+            Problem::Future(datetime) => write!(f, "played in the future, at {datetime}"),
+            Problem::ChannelCase {
+                channel,
+                canonical,
+                rows,
+            } => write!(
+                f,
+                "channel {canonical} also written {channel} ({rows} rows)"
+            ),
+        }
+    }
+}
+
+/// A problem found by [`check`], at a row (starting at 1) unless it is about the whole collection.
+#[derive(Debug, PartialEq)]
+pub struct Issue {
+    /// Row of the problem, starting at 1, if it is about a single row.
+    pub row: Option<usize>,
+    /// What is wrong.
+    pub problem: Problem,
+}
+
+/// Find the problems of the rows of a collection.
+///
+/// Rows that cannot be read as results are only reported for that reason,
+/// while valid rows are also checked against each other and against now.
+pub fn check(rows: &[Row], now: DateTime<Utc>) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut seen: HashMap<&[String], usize> = HashMap::new();
+    // Spellings of each channel with their number of rows, in order of appearance.
+    let mut channels: HashMap<String, Vec<(&str, usize)>> = HashMap::new();
+
+    for (index, row) in rows.iter().enumerate() {
+        let number = index + 1;
+        let mut report = |problem| {
+            issues.push(Issue {
+                row: Some(number),
+                problem,
+            })
+        };
+
+        let result = match row.fields.as_slice() {
+            [_nick, _channel, datetime, timezone] => {
+                if datetime.parse::<DateTime<Utc>>().is_err() {
+                    report(Problem::Timestamp(datetime.clone()));
+                    continue;
+                }
+
+                if timezone.parse::<Tz>().is_err() {
+                    report(Problem::Timezone(timezone.clone()));
+                    continue;
+                }
+
+                match FirstResult::from_fields(&row.fields) {
+                    Ok(result) => result,
+                    Err(_) => continue,
+                }
+            }
+            fields => {
+                report(Problem::Fields(fields.len()));
+                continue;
+            }
+        };
+
+        if let Some(of) = seen.get(row.fields.as_slice()) {
+            report(Problem::Duplicate { of: *of });
+            continue;
+        }
+
+        seen.insert(&row.fields, number);
+
+        if result.datetime > now + FUTURE_TOLERANCE {
+            report(Problem::Future(result.datetime));
+        }
+
+        let spellings = channels.entry(result.channel.to_lowercase()).or_default();
+
+        match spellings
+            .iter_mut()
+            .find(|(spelling, _rows)| *spelling == row.fields[1])
+        {
+            Some((_spelling, rows)) => *rows += 1,
+            None => spellings.push((&row.fields[1], 1)),
+        }
+    }
+
+    for spellings in channels.values().filter(|spellings| spellings.len() > 1) {
+        // The most common spelling wins, then the first one seen.
+        let canonical = spellings
+            .iter()
+            .rev()
+            .max_by_key(|(_spelling, rows)| *rows)
+            .map(|(spelling, _rows)| *spelling)
+            .unwrap_or_default();
+
+        for (spelling, rows) in spellings
+            .iter()
+            .filter(|(spelling, _)| *spelling != canonical)
+        {
+            issues.push(Issue {
+                row: None,
+                problem: Problem::ChannelCase {
+                    channel: String::from(*spelling),
+                    canonical: String::from(canonical),
+                    rows: *rows,
+                },
+            });
+        }
+    }
+
+    issues
+        .into_iter()
+        .sorted_by_key(|issue| (issue.row.is_none(), issue.row))
+        .collect()
+}
+
+/// Fix the problems found by [`check`], returning the rows to keep and the rows removed.
+///
+/// Rows that are not valid results, duplicates and future rows are removed,
+/// and channels are renamed to their canonical casing. Rows that only differed by
+/// the casing of their channel are duplicates once renamed, and are removed too.
+pub fn fix(rows: Vec<Row>, issues: &[Issue]) -> (Vec<Row>, Vec<Row>) {
+    let removed: HashSet<usize> = issues.iter().filter_map(|issue| issue.row).collect();
+    let renames: HashMap<&str, &str> = issues
+        .iter()
+        .filter_map(|issue| match &issue.problem {
+            Problem::ChannelCase {
+                channel, canonical, ..
+            } => Some((channel.as_str(), canonical.as_str())),
+            _ => None,
+        })
+        .collect();
+
+    let mut keep = Vec::new();
+    let mut remove = Vec::new();
+    let mut seen: HashSet<Vec<String>> = HashSet::new();
+
+    for (index, mut row) in rows.into_iter().enumerate() {
+        if let Some(canonical) = row
+            .fields
+            .get(1)
+            .and_then(|channel| renames.get(channel.as_str()))
+        {
+            row.fields[1] = String::from(*canonical);
+        }
+
+        if removed.contains(&(index + 1)) || !seen.insert(row.fields.clone()) {
+            remove.push(row);
+            continue;
+        }
+
+        keep.push(row);
+    }
+
+    (keep, remove)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn row(fields: &[&str]) -> Row {
+        Row {
+            fields: fields.iter().map(|field| String::from(*field)).collect(),
+        }
+    }
+
+    fn result(nick: &str, channel: &str, datetime: &str) -> Row {
+        row(&[nick, channel, datetime, "Europe/Paris"])
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 3, 20, 12, 0, 0).unwrap()
+    }
+
+    fn issue(row: Option<usize>, problem: Problem) -> Issue {
+        Issue { row, problem }
+    }
+
+    fn fields(rows: &[Row]) -> Vec<String> {
+        rows.iter().map(|row| row.fields.join(",")).collect()
+    }
+
+    #[test]
+    fn invalid_rows_are_reported_for_their_first_problem() {
+        let rows = [
+            row(&["alice", "#test"]),
+            row(&["alice", "#test", "yesterday", "Europe/Paris"]),
+            row(&["alice", "#test", "2025-03-14 08:00:00 UTC", "Mars/Olympus"]),
+        ];
+
+        assert_eq!(
+            check(&rows, now()),
+            [
+                issue(Some(1), Problem::Fields(2)),
+                issue(Some(2), Problem::Timestamp(String::from("yesterday"))),
+                issue(Some(3), Problem::Timezone(String::from("Mars/Olympus"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicates_and_rows_from_the_future_are_reported() {
+        let rows = [
+            result("alice", "#test", "2025-03-14 08:00:00 UTC"),
+            result("bob", "#test", "2025-03-14 08:00:01 UTC"),
+            result("alice", "#test", "2025-03-14 08:00:00 UTC"),
+            // Within the tolerance for clock drift.
+            result("carol", "#test", "2025-03-21 08:00:00 UTC"),
+            result("dave", "#test", "2025-03-22 08:00:00 UTC"),
+        ];
+
+        assert_eq!(
+            check(&rows, now()),
+            [
+                issue(Some(3), Problem::Duplicate { of: 1 }),
+                issue(
+                    Some(5),
+                    Problem::Future(Utc.with_ymd_and_hms(2025, 3, 22, 8, 0, 0).unwrap())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_most_common_channel_casing_is_canonical() {
+        let rows = [
+            result("alice", "#Test", "2025-03-14 08:00:00 UTC"),
+            result("bob", "#test", "2025-03-15 08:00:00 UTC"),
+            result("carol", "#test", "2025-03-16 08:00:00 UTC"),
+            result("dave", "#TEST", "2025-03-17 08:00:00 UTC"),
+        ];
+
+        assert_eq!(
+            check(&rows, now())
+                .into_iter()
+                .sorted_by_key(|issue| issue.problem.to_string())
+                .collect::<Vec<_>>(),
+            [
+                issue(
+                    None,
+                    Problem::ChannelCase {
+                        channel: String::from("#TEST"),
+                        canonical: String::from("#test"),
+                        rows: 1,
+                    }
+                ),
+                issue(
+                    None,
+                    Problem::ChannelCase {
+                        channel: String::from("#Test"),
+                        canonical: String::from("#test"),
+                        rows: 1,
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn fixes_remove_the_reported_rows_and_rename_channels() {
+        let rows = vec![
+            result("alice", "#test", "2025-03-14 08:00:00 UTC"),
+            row(&["alice", "#test"]),
+            result("alice", "#test", "2025-03-14 08:00:00 UTC"),
+            result("bob", "#Test", "2025-03-15 08:00:00 UTC"),
+            result("carol", "#test", "2025-03-22 08:00:00 UTC"),
+        ];
+        let issues = check(&rows, now());
+        let (keep, removed) = fix(rows, &issues);
+
+        assert_eq!(
+            fields(&keep),
+            [
+                "alice,#test,2025-03-14 08:00:00 UTC,Europe/Paris",
+                "bob,#test,2025-03-15 08:00:00 UTC,Europe/Paris",
+            ]
+        );
+        assert_eq!(
+            fields(&removed),
+            [
+                "alice,#test",
+                "alice,#test,2025-03-14 08:00:00 UTC,Europe/Paris",
+                "carol,#test,2025-03-22 08:00:00 UTC,Europe/Paris",
+            ]
+        );
+        assert_eq!(check(&keep, now()), []);
+    }
+
+    #[test]
+    fn rows_differing_only_by_channel_casing_are_duplicates_once_renamed() {
+        let rows = vec![
+            result("alice", "#Test", "2025-03-14 08:00:00 UTC"),
+            result("alice", "#test", "2025-03-14 08:00:00 UTC"),
+            result("bob", "#test", "2025-03-15 08:00:00 UTC"),
+        ];
+        let issues = check(&rows, now());
+        let (keep, removed) = fix(rows, &issues);
+
+        assert_eq!(
+            fields(&keep),
+            [
+                "alice,#test,2025-03-14 08:00:00 UTC,Europe/Paris",
+                "bob,#test,2025-03-15 08:00:00 UTC,Europe/Paris",
+            ]
+        );
+        assert_eq!(
+            fields(&removed),
+            ["alice,#test,2025-03-14 08:00:00 UTC,Europe/Paris"]
+        );
+        assert_eq!(check(&keep, now()), []);
+    }
+}
@@ -0,0 +1,871 @@
+use std::path::PathBuf;
+
+use std::ops::Range;
+
+use chrono::{NaiveDate, NaiveTime};
+
+use first_top::Period;
+use first_top::config::Overrides;
+use first_top::output::Format;
+use first_top::query::Query;
+use first_top::ranking::RankMode;
+use first_top::rules::{OpeningMode, parse_range};
+use first_top::schedule::OpeningSchedule;
+use first_top::storage::Backend;
+
+pub const USAGE: &str = "\
+Usage: first_top [OPTIONS] <COMMAND> [ARGS]
+
+Commands:
+  rank      Top !first results (smallest gaps to the opening time of winners)
+  stats     Summary of the attempts and wins in a channel, or personal statistics of a nick
+  history   Daily winners of a channel, most recent first
+  day       Every attempt of a day, with the opening time and the gap to it
+  schedule  Opening times of today and the next days
+  import    Add the results from a CSV file to the collection
+  export    Write the results of the collection as CSV
+  check     Report invalid, duplicate and future rows and inconsistent channel casing
+  opening   Record the opening time the bot drew for a day of a channel
+  migrate   Rewrite the collection in the current schema version, or copy it to another backend
+  help      Show this message
+
+Query options (rank, stats, history, day, schedule, export):
+  -c, --channel <CHANNEL>   Channel to report on (required except for export)
+  -p, --period <PERIOD>     day, daily, week, weekly, month, monthly, year, yearly or all
+      --from <DATE>         Only results from the start of DATE on
+      --to <DATE>           Only results up to the end of DATE
+      --until <DATE>        Only results before the start of DATE
+      --in <DATE>           Only results within DATE (same as --from DATE --to DATE)
+      --tz <TIMEZONE>       Timezone of the period, dates and times shown (default: the channel
+                            timezone or UTC), which does not move the opening time
+  -f, --format <FORMAT>     Output format of the reports: text (default), json, csv or irc
+      --irc                 Pack the reports into IRC messages (same as --format irc)
+      --colours             Show the first three places in bold medal colours with --irc
+  -n, --limit <N>           Maximum number of entries to show
+      --cutoff <MS>         Ignore reactions slower than this many milliseconds
+      --open-hour <START..END>
+                            Range the bot draws the opening hour from
+      --open-min <START..END>
+                            Range the bot draws the opening minute from
+      --schedule <SCHEDULE> How the bot picks the opening time: seeded (by the day of the month,
+                            the default), date-seeded (by the whole date), fixed HH:MM, or
+                            weekdays followed by 7 HH:MM times from Monday to Sunday
+      --opening-mode <MODE> Apply the opening time in the timezone of each player (player, the
+                            default) or in the channel timezone, the same instant for everyone
+                            (channel)
+
+Command options:
+  day <YYYY-MM-DD>          Day to show (before the channel, as in `day 2025-03-14 #channel`)
+  rank [--by <MODE>] [--over <N>]
+                            Order players by fastest win (default), wins, average (of the best N
+                            wins, for players with at least N wins, 5 by default) or points
+                            (10, 7, 5, 3 and 1 for the first five valid attempts of each day),
+                            or bests to show every player's best time, won or not
+  schedule [--days <N>]     Show the opening times of today and the next N days (6 by default,
+                            366 at most), in the --tz timezone or else the channel timezone
+  stats [NICK]              Report on NICK (before the channel, as in `stats nick #channel`)
+  import <FILE>             .csv file with nick, channel, datetime and timezone columns
+  export [-o, --output <FILE>]
+                            Write to a .csv file instead of the standard output
+  opening <YYYY-MM-DD> <HH:MM[:SS]> <CHANNEL> [--rule <RULE>]
+                            Record the opening time of a day, with the rule that drew it
+                            (by default the rule of the channel schedule, like seeded-v1);
+                            recorded times replace the ones computed from the schedule
+  check [--fix]             Remove the reported rows and unify the casing of channels,
+                            unless some rows cannot be read and have to be fixed by hand
+  migrate [--into <BACKEND>]
+                            Copy the collection to the csv or sqlite backend, replacing it there
+
+Global options:
+      --config <FILE>       Config file (default: ~/.config/first_top/config.toml)
+      --data-dir <DIR>      Directory holding the collections
+      --collection <NAME>   Name of the results collection
+      --extension <EXT>     File extension of the collections
+      --strict              Fail on the first invalid row instead of skipping it with a warning
+  -h, --help                Show this message
+  -V, --version             Show the version
+
+Dates can be ISO dates, months or years (2025-03-14, 2025-03, 2025), named months (march,
+march 2025), relative days (now, today, yesterday, 3 weeks ago) or calendar periods (this week,
+last month, last season). The period and the date options cannot be combined.
+
+The channel and period can also be given positionally, as in `first_top rank #channel week`.
+Options are rejected by the commands they do not apply to: the period and date options apply to
+rank, stats, history and export, the limit to rank, stats and history, the output format to the
+commands printing a report (not export, opening, import or migrate) and --colours only with --irc.
+The limit, cutoff, opening ranges, schedule, opening mode and timezone default to the max_results,
+cutoff_ms, open_hour, open_min, schedule, opening_mode and timezone keys of the config file, which
+can also be set per channel in [channels.\"#name\"] tables.
+Set the backups key of the config file to keep that many previous versions of the collection
+(as <file>.1 to <file>.N) whenever import or check --fix rewrites it, and the lock_timeout_ms key
+to change how long to wait for another process using the collection (5000 by default).
+The lock is the .<file>.lock file next to the collection, which only excludes the processes that take
+it: a bot appending to the collection without it can lose its rows to a rewrite, and reads in a
+read-only data directory, where the lock file cannot be created, go without it.
+Queries over a channel or dates read only the days they need, which the .<file>.index file next to
+the collection records (it is rebuilt whenever it is missing or the collection was rewritten).
+Set headers = true to write the collection with a schema version marker and named headers
+instead of the headerless layout of the bot; run migrate to convert an existing collection.
+Opening times recorded with the opening command are kept in the first_openings collection
+(the openings key of the config file) and used instead of drawing them again when ranking.
+Set backend = \"sqlite\" to keep the collections in first_top.sqlite in the data directory instead
+of CSV files (requires first_top built with the sqlite feature); run migrate --into sqlite first.
+
+Exit status:
+  0   Success
+  1   No results found
+  64  Invalid arguments or query
+  65  Invalid data (malformed CSV, unknown timezone)
+  66  File not found
+  74  Could not read or write a file
+  75  The collection is locked by another process
+  77  Permission denied
+  78  Invalid configuration";
+
+/// Number of days after today shown by the schedule command by default, to cover a week.
+const SCHEDULE_DAYS: usize = 6;
+
+/// Most days after today the schedule command shows, a year ahead.
+const MAX_SCHEDULE_DAYS: usize = 366;
+
+/// Commands printing a report, in any format.
+const REPORTS: &[&str] = &["rank", "stats", "history", "day", "schedule", "check"];
+
+/// Commands reading the results of a channel over the dates of the query.
+const RESULTS: &[&str] = &["rank", "stats", "history", "export"];
+
+/// Commands applying the rules of the game to the results or the schedule.
+const RULES: &[&str] = &["rank", "stats", "history", "day", "schedule"];
+
+/// The commands each option applies to, the other options (like --config) applying to all of them.
+const OPTION_COMMANDS: &[(&str, &[&str])] = &[
+    (
+        "--channel",
+        &[
+            "rank", "stats", "history", "day", "schedule", "export", "opening",
+        ],
+    ),
+    ("--period", RESULTS),
+    ("--from", RESULTS),
+    ("--to", RESULTS),
+    ("--until", RESULTS),
+    ("--in", RESULTS),
+    ("--tz", &["rank", "stats", "history", "schedule", "export"]),
+    ("--format", REPORTS),
+    ("--irc", REPORTS),
+    ("--colours", REPORTS),
+    ("--limit", &["rank", "stats", "history"]),
+    ("--cutoff", &["rank", "stats", "history", "day"]),
+    ("--open-hour", RULES),
+    ("--open-min", RULES),
+    (
+        "--schedule",
+        &["rank", "stats", "history", "day", "schedule", "opening"],
+    ),
+    ("--opening-mode", RULES),
+    ("--by", &["rank"]),
+    ("--over", &["rank"]),
+    ("--days", &["schedule"]),
+    ("--output", &["export"]),
+    ("--fix", &["check"]),
+    ("--rule", &["opening"]),
+    ("--into", &["migrate"]),
+];
+
+#[derive(Debug)]
+pub enum Command {
+    Rank(Query),
+    Stats(Query, Option<String>),
+    History(Query),
+    /// Show the opening times of today and this many following days.
+    Schedule(Query, usize),
+    Day(Query, NaiveDate),
+    /// Check the collection, fixing it if asked.
+    Check(Query, bool),
+    /// Migrate the collection in place, or copy it into another backend.
+    Migrate(Option<Backend>),
+    /// Record the opening time of a day, with the rule that drew it if given.
+    Opening(Query, NaiveDate, NaiveTime, Option<String>),
+    /// Import a file, failing on its invalid rows if strict.
+    Import(PathBuf, bool),
+    Export(Query, Option<PathBuf>),
+    Help,
+    Version,
+}
+
+impl Command {
+    /// Name of the command on the command line, rank for the legacy form of the bot.
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Rank(_) => "rank",
+            Command::Stats(..) => "stats",
+            Command::History(_) => "history",
+            Command::Schedule(..) => "schedule",
+            Command::Day(..) => "day",
+            Command::Check(..) => "check",
+            Command::Migrate(_) => "migrate",
+            Command::Opening(..) => "opening",
+            Command::Import(..) => "import",
+            Command::Export(..) => "export",
+            Command::Help => "help",
+            Command::Version => "version",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Cli {
+    pub overrides: Overrides,
+    pub command: Command,
+}
+
+impl Cli {
+    /// Parse the program arguments (without the program name).
+    ///
+    /// When the first argument is not a known command and the second is a channel, the arguments are read
+    /// in the legacy `<ignored> <channel> [period]` form used by the bot, where any unknown period means all time.
+    pub fn parse(args: Vec<String>) -> Result<Self, String> {
+        let mut overrides = Overrides::default();
+        let mut query = Query::default();
+        let mut output = None;
+        let mut fix = false;
+        let mut into = None;
+        let mut rule = None;
+        let mut mode: Option<String> = None;
+        let mut over = None;
+        let mut days = None;
+        let mut positional: Vec<String> = Vec::new();
+        // Options given that only apply to some commands, with those commands.
+        let mut given: Vec<(&str, &[&str])> = Vec::new();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            if arg == "-h" || arg == "--help" {
+                return Ok(Self {
+                    overrides,
+                    command: Command::Help,
+                });
+            }
+
+            if arg == "-V" || arg == "--version" {
+                return Ok(Self {
+                    overrides,
+                    command: Command::Version,
+                });
+            }
+
+            if arg == "--irc" {
+                query.format = Some(Format::Irc);
+                given.extend(option_commands("--irc"));
+                continue;
+            }
+
+            if arg == "--colours" || arg == "--colors" {
+                query.colours = true;
+                given.extend(option_commands("--colours"));
+                continue;
+            }
+
+            if arg == "--fix" {
+                fix = true;
+                given.extend(option_commands("--fix"));
+                continue;
+            }
+
+            if arg == "--strict" {
+                query.strict = true;
+                continue;
+            }
+
+            if arg == "--" {
+                positional.extend(args.by_ref());
+                break;
+            }
+
+            // Channels start with # and negative numbers are values, so only these are options.
+            if !arg.starts_with('-') || arg == "-" {
+                positional.push(arg);
+                continue;
+            }
+
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let value = match inline {
+                Some(value) => value,
+                None => args
+                    .next()
+                    .ok_or_else(|| format!("Option {flag} requires a value"))?,
+            };
+
+            match flag.as_str() {
+                "--config" => overrides.config_file = Some(PathBuf::from(value)),
+                "--data-dir" => overrides.data_dir = Some(PathBuf::from(value)),
+                "--collection" => overrides.collection = Some(value),
+                "--extension" => overrides.extension = Some(value),
+                "-c" | "--channel" => query.channel = Some(value),
+                "-p" | "--period" => query.period = Some(parse_period(&value)?),
+                "-n" | "--limit" => query.limit = Some(parse_number(&flag, &value)?),
+                "--cutoff" => query.cutoff_ms = Some(parse_number(&flag, &value)?),
+                "--open-hour" => query.open_hour = Some(parse_range_option(&flag, &value)?),
+                "--open-min" => query.open_min = Some(parse_range_option(&flag, &value)?),
+                "--schedule" => query.schedule = Some(OpeningSchedule::parse(&value)?),
+                "--opening-mode" => {
+                    query.opening_mode = Some(OpeningMode::from_name(&value).ok_or_else(|| {
+                        format!("Invalid opening mode {value} (expected player or channel)")
+                    })?)
+                }
+                "--from" => query.from = Some(value),
+                "--to" => query.to = Some(value),
+                "--until" => query.until = Some(value),
+                "--in" => query.during = Some(value),
+                "--tz" => {
+                    query.timezone = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Unknown timezone {value}"))?,
+                    )
+                }
+                "-f" | "--format" => {
+                    query.format = Some(Format::from_name(&value).ok_or_else(|| {
+                        format!("Invalid format {value} (expected text, json, csv or irc)")
+                    })?)
+                }
+                "--by" => mode = Some(value),
+                "--over" => {
+                    over = match parse_number(&flag, &value)? {
+                        0 => return Err(String::from("The --over option must be at least 1")),
+                        over => Some(over),
+                    }
+                }
+                "--days" => {
+                    days = match parse_number(&flag, &value)? {
+                        days if days > MAX_SCHEDULE_DAYS => {
+                            return Err(format!(
+                                "The --days option must be at most {MAX_SCHEDULE_DAYS}"
+                            ));
+                        }
+                        days => Some(days),
+                    }
+                }
+                "-o" | "--output" => output = Some(PathBuf::from(value)),
+                "--rule" => rule = Some(value),
+                "--into" => {
+                    into = Some(Backend::from_name(&value).ok_or_else(|| {
+                        format!("Invalid backend {value} (expected csv or sqlite)")
+                    })?)
+                }
+                _ => return Err(format!("Unknown option {flag}")),
+            }
+
+            given.extend(option_commands(match flag.as_str() {
+                "-c" => "--channel",
+                "-p" => "--period",
+                "-n" => "--limit",
+                "-f" => "--format",
+                "-o" => "--output",
+                flag => flag,
+            }));
+        }
+
+        let mut positional = positional.into_iter();
+        let name = positional.next();
+
+        let colours_without_irc = query.colours && query.format != Some(Format::Irc);
+        let mut command = match name.as_deref() {
+            None | Some("help") => Command::Help,
+            Some("rank") => Command::Rank(with_positional(query, positional, true)?),
+            Some("stats") => {
+                let mut positional = positional.peekable();
+                let nick = positional.next_if(|arg| !is_channel(arg));
+
+                Command::Stats(with_positional(query, positional, true)?, nick)
+            }
+            Some("history") => Command::History(with_positional(query, positional, true)?),
+            Some("schedule") => Command::Schedule(
+                with_positional(query, positional, false)?,
+                days.unwrap_or(SCHEDULE_DAYS),
+            ),
+            Some("day") => {
+                let day = positional.next().ok_or("The day command requires a date")?;
+                let day = NaiveDate::parse_from_str(&day, "%Y-%m-%d")
+                    .map_err(|_| format!("Invalid date {day} (expected YYYY-MM-DD)"))?;
+
+                Command::Day(with_positional(query, positional, false)?, day)
+            }
+            Some("export") => Command::Export(with_positional(query, positional, true)?, output),
+            Some("check") => {
+                if let Some(extra) = positional.next() {
+                    return Err(format!("Unexpected argument {extra}"));
+                }
+
+                Command::Check(query, fix)
+            }
+            Some("migrate") => {
+                if let Some(extra) = positional.next() {
+                    return Err(format!("Unexpected argument {extra}"));
+                }
+
+                Command::Migrate(into)
+            }
+            Some("opening") => {
+                let day = positional
+                    .next()
+                    .ok_or("The opening command requires a date")?;
+                let day = NaiveDate::parse_from_str(&day, "%Y-%m-%d")
+                    .map_err(|_| format!("Invalid date {day} (expected YYYY-MM-DD)"))?;
+                let time = positional
+                    .next()
+                    .ok_or("The opening command requires a time")?;
+                let time = NaiveTime::parse_from_str(&time, "%H:%M:%S")
+                    .or_else(|_| NaiveTime::parse_from_str(&time, "%H:%M"))
+                    .map_err(|_| format!("Invalid time {time} (expected HH:MM or HH:MM:SS)"))?;
+                let channel = match (query.channel, positional.next()) {
+                    (Some(channel), None) | (None, Some(channel)) => channel,
+                    (Some(_), Some(extra)) => return Err(format!("Unexpected argument {extra}")),
+                    (None, None) => return Err(String::from("A channel must be provided")),
+                };
+
+                if let Some(extra) = positional.next() {
+                    return Err(format!("Unexpected argument {extra}"));
+                }
+
+                Command::Opening(
+                    Query {
+                        channel: Some(channel),
+                        ..query
+                    },
+                    day,
+                    time,
+                    rule,
+                )
+            }
+            Some("import") => {
+                let file = positional
+                    .next()
+                    .ok_or("The import command requires a file")?;
+
+                if let Some(extra) = positional.next() {
+                    return Err(format!("Unexpected argument {extra}"));
+                }
+
+                Command::Import(PathBuf::from(file), query.strict)
+            }
+            Some(name) => {
+                // Only the bot's calls name a channel after the ignored argument,
+                // so a mistyped command is not taken for one of them.
+                let channel = positional
+                    .next()
+                    .filter(|channel| is_channel(channel))
+                    .ok_or_else(|| format!("Unknown command {name}"))?;
+                let period = positional
+                    .next()
+                    .map(|name| Period::from_name(&name).unwrap_or(Period::Unknown));
+
+                Command::Rank(Query {
+                    channel: Some(channel),
+                    period: period.or(query.period),
+                    ..query
+                })
+            }
+        };
+
+        for (option, commands) in given {
+            if !commands.contains(&command.name()) {
+                return Err(format!(
+                    "The {option} option only applies to {}",
+                    join(commands)
+                ));
+            }
+        }
+
+        if colours_without_irc {
+            return Err(String::from("The --colours option only applies to --irc"));
+        }
+
+        match (&mut command, mode) {
+            (Command::Rank(query), Some(name)) => {
+                query.mode = Some(RankMode::from_name(&name, over).ok_or_else(|| {
+                    format!(
+                        "Invalid mode {name} (expected fastest, wins, average, points or bests)"
+                    )
+                })?);
+            }
+            (Command::Rank(_), None) if over.is_some() => {
+                return Err(String::from(
+                    "The --over option only applies to --by average",
+                ));
+            }
+            (_, Some(_)) => return Err(String::from("The --by option only applies to rank")),
+            (_, None) if over.is_some() => {
+                return Err(String::from("The --over option only applies to rank"));
+            }
+            _ => {}
+        }
+
+        Ok(Self { overrides, command })
+    }
+}
+
+/// Fill the channel, and the period if the command takes one, from the positional arguments of a query command.
+fn with_positional(
+    mut query: Query,
+    mut positional: impl Iterator<Item = String>,
+    takes_period: bool,
+) -> Result<Query, String> {
+    if let Some(channel) = positional.next() {
+        if query.channel.is_some() {
+            return Err(format!("Unexpected argument {channel}"));
+        }
+        query.channel = Some(channel);
+    }
+
+    if takes_period && let Some(period) = positional.next() {
+        if query.period.is_some() {
+            return Err(format!("Unexpected argument {period}"));
+        }
+        query.period = Some(parse_period(&period)?);
+    }
+
+    if let Some(extra) = positional.next() {
+        return Err(format!("Unexpected argument {extra}"));
+    }
+
+    Ok(query)
+}
+
+/// The entry of an option in OPTION_COMMANDS, if it only applies to some commands.
+fn option_commands(option: &str) -> Option<(&'static str, &'static [&'static str])> {
+    OPTION_COMMANDS
+        .iter()
+        .find(|(name, _commands)| *name == option)
+        .copied()
+}
+
+/// Join names as in "rank, stats and history".
+fn join(names: &[&str]) -> String {
+    match names {
+        [] => String::new(),
+        [name] => String::from(*name),
+        [names @ .., last] => format!("{} and {last}", names.join(", ")),
+    }
+}
+
+/// Whether an argument names an IRC channel rather than a nick.
+fn is_channel(arg: &str) -> bool {
+    arg.starts_with(['#', '&', '!', '+'])
+}
+
+fn parse_period(name: &str) -> Result<Period, String> {
+    Period::from_name(name).ok_or_else(|| {
+        format!(
+            "Invalid period {name} (expected day, daily, week, weekly, month, monthly, year, yearly or all)"
+        )
+    })
+}
+
+fn parse_number<N: std::str::FromStr>(flag: &str, value: &str) -> Result<N, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value {value} for {flag}"))
+}
+
+fn parse_range_option(flag: &str, value: &str) -> Result<Range<u32>, String> {
+    parse_range(value)
+        .ok_or_else(|| format!("Invalid range {value} for {flag} (expected start..end)"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Command, String> {
+        Cli::parse(args.split_whitespace().map(String::from).collect()).map(|cli| cli.command)
+    }
+
+    fn channel(query: &Query) -> Option<&str> {
+        query.channel.as_deref()
+    }
+
+    #[test]
+    fn query_commands_take_the_channel_and_period_as_options_or_positionally() {
+        let Ok(Command::Rank(query)) = parse("rank #test week --by points -n 3") else {
+            panic!("expected rank");
+        };
+
+        assert_eq!(channel(&query), Some("#test"));
+        assert!(matches!(query.period, Some(Period::Week)));
+        assert_eq!(
+            query.mode,
+            Some(RankMode::from_name("points", None).unwrap())
+        );
+        assert_eq!(query.limit, Some(3));
+
+        let Ok(Command::History(query)) = parse("history -c #test -p month --format=json") else {
+            panic!("expected history");
+        };
+
+        assert_eq!(channel(&query), Some("#test"));
+        assert!(matches!(query.period, Some(Period::Month)));
+        assert_eq!(query.format, Some(Format::Json));
+
+        assert!(matches!(
+            parse("schedule #test --days 3"),
+            Ok(Command::Schedule(_, 3))
+        ));
+        assert!(matches!(
+            parse("schedule #test"),
+            Ok(Command::Schedule(_, SCHEDULE_DAYS))
+        ));
+        assert!(
+            matches!(parse("export --output out.csv"), Ok(Command::Export(query, Some(_))) if query.channel.is_none())
+        );
+        assert!(matches!(parse("check --fix"), Ok(Command::Check(_, true))));
+        assert!(matches!(parse("check"), Ok(Command::Check(_, false))));
+        assert!(matches!(
+            parse("migrate --into sqlite"),
+            Ok(Command::Migrate(Some(Backend::Sqlite)))
+        ));
+        assert!(matches!(
+            parse("import results.csv --strict"),
+            Ok(Command::Import(_, true))
+        ));
+    }
+
+    #[test]
+    fn stats_take_an_optional_nick_before_the_channel() {
+        assert!(matches!(
+            parse("stats alice #test"),
+            Ok(Command::Stats(query, Some(nick))) if nick == "alice" && channel(&query) == Some("#test")
+        ));
+        assert!(matches!(
+            parse("stats #test year"),
+            Ok(Command::Stats(query, None)) if matches!(query.period, Some(Period::Year))
+        ));
+    }
+
+    #[test]
+    fn days_and_openings_take_a_date_first() {
+        assert!(matches!(
+            parse("day 2025-03-14 #test"),
+            Ok(Command::Day(query, day))
+                if day == NaiveDate::from_ymd_opt(2025, 3, 14).unwrap() && channel(&query) == Some("#test")
+        ));
+        assert!(matches!(
+            parse("opening 2025-03-14 09:30 #test --rule fixed"),
+            Ok(Command::Opening(query, _, time, Some(rule)))
+                if time == NaiveTime::from_hms_opt(9, 30, 0).unwrap()
+                    && rule == "fixed"
+                    && channel(&query) == Some("#test")
+        ));
+        assert!(parse("day 14/03/2025 #test").is_err());
+        assert!(parse("opening 2025-03-14 9h30 #test").is_err());
+        assert!(parse("opening 2025-03-14 09:30").is_err());
+    }
+
+    #[test]
+    fn help_and_version_are_commands_too() {
+        assert!(matches!(parse(""), Ok(Command::Help)));
+        assert!(matches!(parse("help"), Ok(Command::Help)));
+        assert!(matches!(parse("rank #test --help"), Ok(Command::Help)));
+        assert!(matches!(parse("-V"), Ok(Command::Version)));
+    }
+
+    #[test]
+    fn the_bot_calls_with_an_ignored_argument_a_channel_and_a_period() {
+        let Ok(Command::Rank(query)) = parse("!top #test weekly") else {
+            panic!("expected the legacy rank");
+        };
+
+        assert_eq!(channel(&query), Some("#test"));
+        assert!(matches!(query.period, Some(Period::Weekly)));
+
+        // Unknown periods mean all time, as they always have for the bot.
+        let Ok(Command::Rank(query)) = parse("!top #test forever") else {
+            panic!("expected the legacy rank");
+        };
+
+        assert!(matches!(query.period, Some(Period::Unknown)));
+
+        let Ok(Command::Rank(query)) = parse("!top #test") else {
+            panic!("expected the legacy rank");
+        };
+
+        assert!(query.period.is_none());
+
+        let Ok(Command::Rank(query)) = parse("!top #test --by average --over 3") else {
+            panic!("expected the legacy rank");
+        };
+
+        assert_eq!(
+            query.mode,
+            Some(RankMode::from_name("average", Some(3)).unwrap())
+        );
+    }
+
+    #[test]
+    fn mistyped_commands_are_not_legacy_calls() {
+        assert_eq!(parse("rnak").unwrap_err(), "Unknown command rnak");
+        assert_eq!(
+            parse("stast alice #test").unwrap_err(),
+            "Unknown command stast"
+        );
+    }
+
+    #[test]
+    fn options_of_other_commands_are_rejected() {
+        for (args, error) in [
+            ("rank #test --fix", "The --fix option only applies to check"),
+            (
+                "rank #test --days 3",
+                "The --days option only applies to schedule",
+            ),
+            (
+                "rank #test -o out.csv",
+                "The --output option only applies to export",
+            ),
+            (
+                "rank #test --rule fixed",
+                "The --rule option only applies to opening",
+            ),
+            (
+                "check --into csv",
+                "The --into option only applies to migrate",
+            ),
+            (
+                "rank #test --over 3",
+                "The --over option only applies to --by average",
+            ),
+            (
+                "history #test --by wins",
+                "The --by option only applies to rank",
+            ),
+            (
+                "stats alice #test --by average --over 3",
+                "The --by option only applies to rank",
+            ),
+            (
+                "history #test --over 3",
+                "The --over option only applies to rank",
+            ),
+            (
+                "rank #test --by average --over 0",
+                "The --over option must be at least 1",
+            ),
+            (
+                "schedule #test --days 367",
+                "The --days option must be at most 366",
+            ),
+            ("rank #test --frobnicate 1", "Unknown option --frobnicate"),
+            ("rank #test --limit", "Option --limit requires a value"),
+            ("rank #test --limit ten", "Invalid value ten for --limit"),
+            (
+                "rank #test fortnight",
+                "Invalid period fortnight (expected day, daily, week, weekly, month, monthly, year, yearly or all)",
+            ),
+            ("rank #test week extra", "Unexpected argument extra"),
+            ("rank #test -c #other", "Unexpected argument #test"),
+            ("migrate sqlite", "Unexpected argument sqlite"),
+        ] {
+            assert_eq!(parse(args).unwrap_err(), error, "{args}");
+        }
+    }
+
+    #[test]
+    fn options_apply_to_their_commands_only() {
+        for (args, error) in [
+            (
+                "rank #test --colours",
+                "The --colours option only applies to --irc",
+            ),
+            (
+                "schedule #test --days 1 --from 2025-01-01",
+                "The --from option only applies to rank, stats, history and export",
+            ),
+            (
+                "day 2025-03-14 #test -n 1",
+                "The --limit option only applies to rank, stats and history",
+            ),
+            (
+                "schedule #test -p week",
+                "The --period option only applies to rank, stats, history and export",
+            ),
+            (
+                "day 2025-03-14 #test --to today",
+                "The --to option only applies to rank, stats, history and export",
+            ),
+            (
+                "schedule #test --until today",
+                "The --until option only applies to rank, stats, history and export",
+            ),
+            (
+                "opening 2025-03-14 09:30 #test --in 2025",
+                "The --in option only applies to rank, stats, history and export",
+            ),
+            (
+                "day 2025-03-14 #test --tz Europe/Paris",
+                "The --tz option only applies to rank, stats, history, schedule and export",
+            ),
+            (
+                "export --irc",
+                "The --irc option only applies to rank, stats, history, day, schedule and check",
+            ),
+            (
+                "export -f json",
+                "The --format option only applies to rank, stats, history, day, schedule and check",
+            ),
+            (
+                "opening 2025-03-14 09:30 #test --irc --colours",
+                "The --irc option only applies to rank, stats, history, day, schedule and check",
+            ),
+            (
+                "import results.csv -c #test",
+                "The --channel option only applies to rank, stats, history, day, schedule, export and opening",
+            ),
+            (
+                "opening 2025-03-14 09:30 #test --cutoff 500",
+                "The --cutoff option only applies to rank, stats, history and day",
+            ),
+            (
+                "export --open-hour 5..12",
+                "The --open-hour option only applies to rank, stats, history, day and schedule",
+            ),
+            (
+                "export --open-min 0..59",
+                "The --open-min option only applies to rank, stats, history, day and schedule",
+            ),
+            (
+                "export --opening-mode channel",
+                "The --opening-mode option only applies to rank, stats, history, day and schedule",
+            ),
+            (
+                "migrate --schedule=seeded",
+                "The --schedule option only applies to rank, stats, history, day, schedule and opening",
+            ),
+            ("schedule #test week", "Unexpected argument week"),
+            ("check #test", "Unexpected argument #test"),
+        ] {
+            assert_eq!(parse(args).unwrap_err(), error, "{args}");
+        }
+
+        for args in [
+            "rank #test week --irc --colours -n 3 --cutoff 500 --open-hour 5..12 --open-min 0..59 \
+             --schedule=seeded --opening-mode channel --tz UTC --by wins",
+            "!top #test weekly -n 3",
+            "stats alice #test --from 2025-01-01 --to 2025-02-01 -f json",
+            "history #test --in 2025 --format=irc --colors",
+            "day 2025-03-14 #test --cutoff 500 --opening-mode player --irc",
+            "schedule #test --days 1 --tz UTC --schedule=seeded --format irc --colours",
+            "export -c #test --until today --tz UTC -o out.csv",
+            "check --fix --format json",
+            "opening 2025-03-14 09:30 #test --schedule=seeded --rule fixed",
+            "migrate --into csv --strict",
+            "import results.csv --strict --data-dir data",
+        ] {
+            assert!(parse(args).is_ok(), "{args}: {:?}", parse(args));
+        }
+    }
+}
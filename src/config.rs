@@ -0,0 +1,511 @@
+//! Settings of first_top, layered from defaults, the TOML config file, environment variables
+//! and the command line, with the rules of each channel.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::database::{Database, LOCK_TIMEOUT};
+use crate::error::{Error, Result};
+use crate::rules::{OpeningMode, RuleOverrides, Rules, parse_range};
+use crate::schedule::OpeningSchedule;
+#[cfg(feature = "sqlite")]
+use crate::sqlite::SqliteDatabase;
+use crate::storage::Backend;
+#[cfg(feature = "sqlite")]
+use crate::storage::SQLITE_FILE;
+
+const DEFAULT_DATA_DIR: &str = "/home/gluon/var/irc/bots/Vettel/data/";
+const DEFAULT_COLLECTION: &str = "first_results";
+const DEFAULT_EXTENSION: &str = "csv";
+const DEFAULT_OPENINGS: &str = "first_openings";
+
+const ENV_CONFIG: &str = "FIRST_TOP_CONFIG";
+const ENV_DATA_DIR: &str = "FIRST_TOP_DATA_DIR";
+const ENV_COLLECTION: &str = "FIRST_TOP_COLLECTION";
+const ENV_EXTENSION: &str = "FIRST_TOP_EXTENSION";
+
+/// Values given explicitly on the command line, which take precedence over everything else.
+#[derive(Debug, Default)]
+pub struct Overrides {
+    /// Config file to read instead of the one of FIRST_TOP_CONFIG or the default location.
+    pub config_file: Option<PathBuf>,
+    /// Directory of the collections.
+    pub data_dir: Option<PathBuf>,
+    /// Name of the collection of results.
+    pub collection: Option<String>,
+    /// Extension of the collection files.
+    pub extension: Option<String>,
+}
+
+/// Resolved settings used to open the database and rank the results.
+#[derive(Debug)]
+pub struct Config {
+    /// Directory of the collections.
+    pub data_dir: PathBuf,
+    /// Name of the collection of results.
+    pub collection: String,
+    /// Extension of the collection files.
+    pub extension: String,
+    /// Name of the collection of the opening times recorded by the bot.
+    pub openings: String,
+    /// What the collections are stored in.
+    pub backend: Backend,
+    /// Number of previous versions of the collection kept when it is rewritten.
+    pub backups: usize,
+    /// Write the collection with a schema version marker and named headers.
+    pub headers: bool,
+    /// How long to wait for another process to release the collection.
+    pub lock_timeout: Duration,
+    /// Rules set at the root of the config file, shared by all channels.
+    pub rules: RuleOverrides,
+    /// Rules of the `[channels."#name"]` tables, keyed by lowercase channel name.
+    pub channels: HashMap<String, RuleOverrides>,
+}
+
+/// The keys of a config file, each replacing the default when present: settings and rules at the root,
+/// and the rules of each channel in its `[channels."#name"]` table.
+///
+/// Unknown keys and tables are rejected, as they would do nothing.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    data_dir: Option<PathBuf>,
+    collection: Option<String>,
+    extension: Option<String>,
+    openings: Option<String>,
+    backend: Option<String>,
+    backups: Option<usize>,
+    headers: Option<bool>,
+    lock_timeout_ms: Option<u64>,
+    // Root keys taken by neither struct are still rejected as unknown.
+    #[serde(flatten)]
+    rules: FileRules,
+    #[serde(default)]
+    channels: HashMap<String, FileRules>,
+}
+
+/// The rule keys of a table of a config file, see [`RuleOverrides`].
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileRules {
+    max_results: Option<usize>,
+    cutoff_ms: Option<i64>,
+    open_hour: Option<String>,
+    open_min: Option<String>,
+    schedule: Option<String>,
+    opening_mode: Option<String>,
+    timezone: Option<String>,
+}
+
+impl Config {
+    /// Resolve the configuration by layering, from lowest to highest precedence:
+    /// built-in defaults, the TOML config file, environment variables and command line overrides.
+    pub fn load(overrides: &Overrides) -> Result<Self> {
+        let mut config = Self::defaults();
+
+        if let Some(path) = config_file(overrides)? {
+            config.apply_file(parse_file(&path)?, &path)?;
+        }
+
+        if let Some(data_dir) = env_var(ENV_DATA_DIR) {
+            config.data_dir = PathBuf::from(data_dir);
+        }
+        if let Some(collection) = env_var(ENV_COLLECTION) {
+            config.collection = collection;
+        }
+        if let Some(extension) = env_var(ENV_EXTENSION) {
+            config.extension = extension;
+        }
+
+        if let Some(data_dir) = &overrides.data_dir {
+            config.data_dir = data_dir.clone();
+        }
+        if let Some(collection) = &overrides.collection {
+            config.collection = collection.clone();
+        }
+        if let Some(extension) = &overrides.extension {
+            config.extension = extension.clone();
+        }
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// The built-in defaults, before any config file, environment variable or override.
+    fn defaults() -> Self {
+        Self {
+            data_dir: PathBuf::from(DEFAULT_DATA_DIR),
+            collection: String::from(DEFAULT_COLLECTION),
+            extension: String::from(DEFAULT_EXTENSION),
+            openings: String::from(DEFAULT_OPENINGS),
+            backend: Backend::Csv,
+            backups: 0,
+            headers: false,
+            lock_timeout: LOCK_TIMEOUT,
+            rules: RuleOverrides::default(),
+            channels: HashMap::new(),
+        }
+    }
+
+    /// Layer the keys of a config file over the config.
+    fn apply_file(&mut self, file: FileConfig, path: &Path) -> Result<()> {
+        let context = |key: &str| format!("{}: {key}", path.display());
+
+        if let Some(data_dir) = file.data_dir {
+            self.data_dir = data_dir;
+        }
+        if let Some(collection) = file.collection {
+            self.collection = collection;
+        }
+        if let Some(extension) = file.extension {
+            self.extension = extension;
+        }
+        if let Some(openings) = file.openings {
+            self.openings = openings;
+        }
+        if let Some(name) = file.backend {
+            self.backend = Backend::from_name(&name).ok_or_else(|| {
+                Error::Config(format!(
+                    "{}: invalid backend {name} (expected csv or sqlite)",
+                    context("backend")
+                ))
+            })?;
+        }
+        if let Some(backups) = file.backups {
+            self.backups = backups;
+        }
+        if let Some(headers) = file.headers {
+            self.headers = headers;
+        }
+        if let Some(lock_timeout_ms) = file.lock_timeout_ms {
+            self.lock_timeout = Duration::from_millis(lock_timeout_ms);
+        }
+
+        file.rules.apply(&mut self.rules, &context)?;
+
+        for (channel, rules) in file.channels {
+            let context = |key: &str| format!("{}: [channels.\"{channel}\"] {key}", path.display());
+            let mut overrides = RuleOverrides::default();
+
+            rules.apply(&mut overrides, &context)?;
+            self.channels.insert(channel.to_lowercase(), overrides);
+        }
+
+        Ok(())
+    }
+
+    /// The CSV database of the data directory, with the backups, lock timeout and layout of the config.
+    pub fn database(&self) -> Result<Database> {
+        let path = self.data_dir.to_str().ok_or_else(|| {
+            Error::Config(format!(
+                "Invalid data directory {}",
+                self.data_dir.display()
+            ))
+        })?;
+
+        Ok(Database::new(path, Some(&self.extension))
+            .with_backups(self.backups)
+            .with_lock_timeout(self.lock_timeout)
+            .with_headers(self.headers))
+    }
+
+    /// The SQLite database of the data directory, with the lock timeout of the config.
+    #[cfg(feature = "sqlite")]
+    pub fn sqlite(&self) -> SqliteDatabase {
+        SqliteDatabase::new(self.data_dir.join(SQLITE_FILE)).with_lock_timeout(self.lock_timeout)
+    }
+
+    /// The rules of a channel: the defaults, overridden by the root and then the channel config.
+    pub fn rules(&self, channel: Option<&str>) -> Rules {
+        let mut rules = Rules::default();
+
+        self.rules.apply(&mut rules);
+
+        if let Some(overrides) =
+            channel.and_then(|channel| self.channels.get(&channel.to_lowercase()))
+        {
+            overrides.apply(&mut rules);
+        }
+
+        rules
+    }
+
+    fn validate(&self) -> Result<()> {
+        let hint = format!("set it with --data-dir, {ENV_DATA_DIR} or data_dir in the config file");
+
+        match fs::metadata(&self.data_dir) {
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(_) => {
+                return Err(Error::Config(format!(
+                    "Data path {} is not a directory ({hint})",
+                    self.data_dir.display()
+                )));
+            }
+            Err(_) => {
+                return Err(Error::Config(format!(
+                    "Data directory {} does not exist ({hint})",
+                    self.data_dir.display()
+                )));
+            }
+        }
+
+        if self.collection.is_empty() {
+            return Err(Error::Config(String::from(
+                "The collection name cannot be empty",
+            )));
+        }
+
+        if self.openings.is_empty() || self.openings == self.collection {
+            return Err(Error::Config(String::from(
+                "The openings collection must be named, and differently from the results collection",
+            )));
+        }
+
+        if self.extension.is_empty() {
+            return Err(Error::Config(String::from(
+                "The file extension cannot be empty",
+            )));
+        }
+
+        self.backend.available()?;
+
+        self.rules(None).validate().map_err(Error::Config)?;
+
+        for channel in self.channels.keys() {
+            self.rules(Some(channel))
+                .validate()
+                .map_err(|error| Error::Config(format!("[channels.\"{channel}\"] {error}")))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Find the config file to read, if any.
+///
+/// An explicitly requested file (flag or environment variable) must exist,
+/// while the default location under the user's config directory is optional.
+fn config_file(overrides: &Overrides) -> Result<Option<PathBuf>> {
+    let explicit = overrides
+        .config_file
+        .clone()
+        .or_else(|| env_var(ENV_CONFIG).map(PathBuf::from));
+
+    if let Some(path) = explicit {
+        return match path.is_file() {
+            true => Ok(Some(path)),
+            false => Err(Error::Config(format!(
+                "Config file {} does not exist",
+                path.display()
+            ))),
+        };
+    }
+
+    let base = env_var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env_var("HOME").map(|home| Path::new(&home).join(".config")));
+
+    Ok(base
+        .map(|base| base.join("first_top").join("config.toml"))
+        .filter(|path| path.is_file()))
+}
+
+impl FileRules {
+    /// Layer the rules of the table over overrides, parsing their values.
+    fn apply(self, overrides: &mut RuleOverrides, context: &dyn Fn(&str) -> String) -> Result<()> {
+        let range = |key: &str, value: String| {
+            parse_range(&value).ok_or_else(|| {
+                Error::Config(format!(
+                    "{}: invalid range {value} (expected start..end)",
+                    context(key)
+                ))
+            })
+        };
+
+        if let Some(max_results) = self.max_results {
+            overrides.max_results = Some(max_results);
+        }
+        if let Some(cutoff_ms) = self.cutoff_ms {
+            overrides.cutoff_us = Some(cutoff_ms.checked_mul(1000).ok_or_else(|| {
+                Error::Config(format!("{} is out of range", context("cutoff_ms")))
+            })?);
+        }
+        if let Some(open_hour) = self.open_hour {
+            overrides.open_hour = Some(range("open_hour", open_hour)?);
+        }
+        if let Some(open_min) = self.open_min {
+            overrides.open_min = Some(range("open_min", open_min)?);
+        }
+        if let Some(schedule) = self.schedule {
+            overrides.schedule = Some(
+                OpeningSchedule::parse(&schedule)
+                    .map_err(|error| Error::Config(format!("{}: {error}", context("schedule"))))?,
+            );
+        }
+        if let Some(opening_mode) = self.opening_mode {
+            overrides.opening_mode =
+                Some(OpeningMode::from_name(&opening_mode).ok_or_else(|| {
+                    Error::Config(format!(
+                        "{}: invalid opening mode {opening_mode} (expected player or channel)",
+                        context("opening_mode")
+                    ))
+                })?);
+        }
+        if let Some(timezone) = self.timezone {
+            overrides.timezone = Some(timezone.parse().map_err(|_| {
+                Error::Config(format!(
+                    "{}: unknown timezone {timezone}",
+                    context("timezone")
+                ))
+            })?);
+        }
+
+        Ok(())
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn parse_file(path: &Path) -> Result<FileConfig> {
+    let contents = fs::read_to_string(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    parse(&contents, path)
+}
+
+/// Read the keys of a config file, failing at the line of the first invalid one when it is known.
+fn parse(contents: &str, path: &Path) -> Result<FileConfig> {
+    toml::from_str(contents).map_err(|error| {
+        Error::Config(match error.span().filter(|span| !span.is_empty()) {
+            Some(span) => format!(
+                "{}:{}: {}",
+                path.display(),
+                contents[..span.start].matches('\n').count() + 1,
+                error.message()
+            ),
+            None => format!("{}: {}", path.display(), error.message()),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(contents: &str) -> Result<Config> {
+        let path = Path::new("config.toml");
+        let mut config = Config::defaults();
+
+        config.apply_file(parse(contents, path)?, path)?;
+
+        Ok(config)
+    }
+
+    fn message(result: Result<Config>) -> String {
+        match result {
+            Err(Error::Config(message)) => message,
+            other => panic!("expected a config error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn quoted_table_headers_name_channels() {
+        let config = apply(
+            "max_results = 5\n\
+             [channels.\"#First\"]\n\
+             max_results = 3\n\
+             [ channels . '#with.dot' ]\n\
+             schedule = \"fixed 09:00\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.rules.max_results, Some(5));
+        assert_eq!(config.channels["#first"].max_results, Some(3));
+        assert_eq!(
+            config.channels["#with.dot"].schedule,
+            Some(OpeningSchedule::parse("fixed 09:00").unwrap())
+        );
+    }
+
+    #[test]
+    fn literal_strings_and_hashes_in_strings_are_values() {
+        let config = apply(
+            "# a comment\n\
+             data_dir = '/tmp/#data' # the data # directory\n\
+             collection = \"a \\\"#\\\" b\"#comment\n\
+             [channels.'#x'] # the channel\n\
+             timezone = 'Europe/Paris'\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.data_dir, PathBuf::from("/tmp/#data"));
+        assert_eq!(config.collection, "a \"#\" b");
+        assert_eq!(
+            config.channels["#x"].timezone,
+            Some(chrono_tz::Europe::Paris)
+        );
+    }
+
+    #[test]
+    fn values_must_have_the_type_of_their_key() {
+        let config = apply("backups = 1_000\nheaders = true\ncutoff_ms = 500\n").unwrap();
+
+        assert_eq!(config.backups, 1000);
+        assert!(config.headers);
+        assert_eq!(config.rules.cutoff_us, Some(500_000));
+        assert_eq!(
+            message(apply("headers = \"yes\"\n")),
+            "config.toml:1: invalid type: string \"yes\", expected a boolean"
+        );
+        assert_eq!(
+            message(apply("backups = -1\n")),
+            "config.toml:1: invalid value: integer `-1`, expected usize"
+        );
+        assert_eq!(
+            message(apply("[channels.\"#x\"]\nopen_hour = 5\n")),
+            "config.toml:2: invalid type: integer `5`, expected a string"
+        );
+        assert_eq!(
+            message(apply("cutoff_ms = 9223372036854776\n")),
+            "config.toml: cutoff_ms is out of range"
+        );
+    }
+
+    #[test]
+    fn unknown_keys_and_tables_are_rejected() {
+        assert_eq!(
+            message(apply("cutof_ms = 500\n")),
+            "config.toml: unknown field `cutof_ms`"
+        );
+        assert!(
+            message(apply("[channels.\"#x\"]\ncutof_ms = 500\n"))
+                .starts_with("config.toml:2: unknown field `cutof_ms`, expected one of")
+        );
+        assert_eq!(
+            message(apply("[channel.\"#x\"]\ncutoff_ms = 500\n")),
+            "config.toml: unknown field `channel`"
+        );
+        assert!(
+            message(apply("[channels.\"#x\".y]\ncutoff_ms = 500\n")).contains("unknown field `y`")
+        );
+    }
+
+    #[test]
+    fn invalid_toml_is_rejected() {
+        assert!(apply("backups = 1\nbackups = 2\n").is_err());
+        assert!(apply("collection = \"a\\qb\"\n").is_err());
+        assert!(apply("[channels.\"#x]\n").is_err());
+        assert!(apply("backups\n").is_err());
+        assert!(apply("bad key = 1\n").is_err());
+    }
+}
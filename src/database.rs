@@ -1,31 +1,245 @@
-use std::cmp::PartialEq;
-use std::error::Error;
-use std::fmt;
-use std::fs::File;
-use std::io::ErrorKind;
+//! Collections stored as CSV files, the way the bot writes them, read and written under a lock.
 
-#[derive(Debug)]
-struct CsvDbError(String);
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Take, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::FirstResult;
+use crate::dates::DateRange;
+use crate::error::{Error, Result};
+use crate::index::{Index, Position, Span};
+use crate::storage::{Storage, in_range};
+
+/// How long to wait for the lock of a collection by default.
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a busy lock is tried again.
+const LOCK_RETRY: Duration = Duration::from_millis(50);
+
+/// First line of a versioned collection, followed by the version and then a line of headers.
+///
+/// Collections without it are headerless files of version 1, the layout the bot writes.
+const SCHEMA_MARKER: &str = "#schema ";
+
+/// A record stored as a row of fields in a collection.
+pub trait CsvRecord: Sized {
+    /// Names of the fields returned by to_fields, used as the headers of versioned collections.
+    const HEADERS: &'static [&'static str] = &[];
+    /// Version of the layout of to_fields, to be increased whenever fields are added.
+    const VERSION: u32 = 1;
 
-impl fmt::Display for CsvDbError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+    /// Build a record from the fields of a row, or describe why the row is invalid.
+    fn from_fields(fields: &[String]) -> std::result::Result<Self, String>;
+    /// The fields of the row of the record, in the order of HEADERS.
+    fn to_fields(&self) -> Vec<String>;
+
+    /// Convert the fields of a row written by an older version to the current layout.
+    ///
+    /// Fields of versioned collections are already in the order of HEADERS,
+    /// with the columns the file does not have left empty.
+    fn migrate(version: u32, fields: Vec<String>) -> std::result::Result<Vec<String>, String> {
+        let _ = version;
+
+        Ok(fields)
     }
 }
 
-impl Error for CsvDbError {}
+type Records = csv::StringRecordsIntoIter<Take<File>>;
 
-pub trait CsvRecord {
-    fn from_fields(fields: &[String]) -> Self;
-    fn to_fields(&self) -> Vec<String>;
+/// Iterator over the entities of a collection matching a filter, reading one row at a time.
+///
+/// Rows that cannot be read are yielded as errors, so callers decide whether to stop or skip them.
+pub struct SelectIter<T, P> {
+    path: PathBuf,
+    file: Option<File>,
+    records: Option<Records>,
+    /// Part of the collection read by records.
+    span: Span,
+    /// Parts of the collection left to read, the next one last.
+    spans: Vec<Span>,
+    /// Schema version of the collection.
+    version: u32,
+    /// Column of each of the headers of T, for versioned collections.
+    columns: Option<Vec<Option<usize>>>,
+    where_filter: P,
+    entity: PhantomData<T>,
+    /// Shared lock of the collection, held until the iterator is dropped.
+    _lock: Option<File>,
+}
+
+impl<T, P> Iterator for SelectIter<T, P>
+where
+    T: CsvRecord,
+    P: FnMut(&T) -> bool,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.next_row()? {
+                (Ok(entity), _) if !(self.where_filter)(&entity) => continue,
+                (entity, _) => return Some(entity),
+            }
+        }
+    }
+}
+
+impl<T, P> SelectIter<T, P>
+where
+    T: CsvRecord,
+{
+    /// Read the next row of the parts of the collection left, with where it starts.
+    fn next_row(&mut self) -> Option<(Result<T>, Position)> {
+        loop {
+            let record = match self.records.as_mut().and_then(Iterator::next) {
+                Some(record) => record,
+                None => {
+                    let span = self.spans.pop()?;
+
+                    match self.read_span(span) {
+                        Ok(records) => self.records = Some(records),
+                        Err(error) => {
+                            self.spans.clear();
+                            let position = Position {
+                                offset: span.start,
+                                line: span.line,
+                            };
+
+                            return Some((Err(error), position));
+                        }
+                    }
+                    self.span = span;
+                    continue;
+                }
+            };
+            // Positions in the file, from those in the part of it read.
+            let position = match record.as_ref().ok().and_then(|record| record.position()) {
+                Some(position) => Position {
+                    offset: self.span.start + position.byte(),
+                    line: self.span.line + position.line() - 1,
+                },
+                None => Position {
+                    offset: self.span.start,
+                    line: self.span.line,
+                },
+            };
+            let entity = match record {
+                Ok(record) => {
+                    let fields: Vec<String> = match &self.columns {
+                        Some(columns) => columns
+                            .iter()
+                            .map(|column| {
+                                String::from(
+                                    column
+                                        .and_then(|column| record.get(column))
+                                        .unwrap_or_default(),
+                                )
+                            })
+                            .collect(),
+                        None => record.iter().map(String::from).collect(),
+                    };
+                    let fields = match self.version < T::VERSION {
+                        true => T::migrate(self.version, fields),
+                        false => Ok(fields),
+                    };
+
+                    fields
+                        .and_then(|fields| T::from_fields(&fields))
+                        .map_err(|message| Error::Record {
+                            path: self.path.clone(),
+                            line: position.line,
+                            message,
+                        })
+                }
+                // Lines in the file, from those in the part of it read.
+                Err(error) => Err(match Error::csv(&self.path, error) {
+                    Error::Csv { path, line, source } => Error::Csv {
+                        path,
+                        line: line.map(|line| self.span.line + line - 1),
+                        source,
+                    },
+                    error => error,
+                }),
+            };
+
+            return Some((entity, position));
+        }
+    }
+
+    /// Read the rows of a part of the collection, after the headers.
+    fn read_span(&self, span: Span) -> Result<Records> {
+        let io_error = |source| Error::Io {
+            path: self.path.clone(),
+            source,
+        };
+        let mut file = match &self.file {
+            Some(file) => file.try_clone().map_err(io_error)?,
+            None => return Err(io_error(io::Error::from(ErrorKind::NotFound))),
+        };
+
+        file.seek(SeekFrom::Start(span.start)).map_err(io_error)?;
+
+        Ok(csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(
+                file.take(
+                    span.end
+                        .map_or(u64::MAX, |end| end.saturating_sub(span.start)),
+                ),
+            )
+            .into_records())
+    }
+
+    /// Where the rows read so far end, and the number of lines up to there.
+    fn end(&self) -> (u64, u64) {
+        match &self.records {
+            Some(records) => {
+                let position = records.reader().position();
+
+                (
+                    self.span.start + position.byte(),
+                    self.span.line - 1 + position.line() - 1,
+                )
+            }
+            None => (self.span.start, self.span.line - 1),
+        }
+    }
 }
 
+/// Lock of a collection taken with [`Database::lock_shared`] or [`Database::lock_exclusive`],
+/// released when dropped.
+#[derive(Debug)]
+pub struct Lock {
+    _file: Option<File>,
+}
+
+/// Collections stored as CSV files in a directory, one `<collection>.<extension>` file each.
+///
+/// Reads take a shared lock and writes an exclusive one on the `.<collection>.<extension>.lock` file
+/// next to the collection. The lock is advisory: it only excludes the processes that take it,
+/// which are first_top and programs writing through this type ([`Storage::insert`]) or holding
+/// [`Database::lock_exclusive`] while they write. A bot appending to the file on its own is not excluded,
+/// so a rewrite (import, check --fix, migrate) can lose the rows it appends meanwhile.
+/// Readers that cannot create the lock file, in a read-only directory, read without the lock.
+///
+/// Range selections of results read only the days they need, as the `.<collection>.<extension>.index`
+/// file records, which is brought up to date on each of them and removed whenever the collection is rewritten.
 pub struct Database {
     path: String,
     extension: String,
+    /// Number of previous versions of a collection kept by write.
+    backups: usize,
+    lock_timeout: Duration,
+    /// Write collections with the schema marker and a line of headers.
+    headers: bool,
 }
 
 impl Database {
+    /// A database of the collections in the directory at path, as files with the extension (csv by default).
     pub fn new(path: &str, extension: Option<&str>) -> Self {
         let extension = match extension {
             None => "csv",
@@ -35,146 +249,1174 @@ impl Database {
         Self {
             path: String::from(path),
             extension: String::from(extension),
+            backups: 0,
+            lock_timeout: LOCK_TIMEOUT,
+            headers: false,
         }
     }
 
-    pub fn select<T, P>(
+    /// Write collections with a schema version marker and named headers, instead of the bot's headerless layout.
+    ///
+    /// Records of a version above 1 are always written that way.
+    pub fn with_headers(mut self, headers: bool) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Keep the previous versions of a collection when writing it, as `<file>.1` (the latest) to `<file>.<backups>`.
+    pub fn with_backups(mut self, backups: usize) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    /// Wait at most lock_timeout for the lock of a collection before giving up.
+    pub fn with_lock_timeout(mut self, lock_timeout: Duration) -> Self {
+        self.lock_timeout = lock_timeout;
+        self
+    }
+
+    /// Take the shared lock of a collection, to read it as a whole while no one writes it.
+    ///
+    /// It is not taken, without an error, when the lock file cannot be created in a read-only directory.
+    pub fn lock_shared(&self, collection: &str) -> Result<Lock> {
+        Ok(Lock {
+            _file: self.lock(collection, false)?,
+        })
+    }
+
+    /// Take the exclusive lock of a collection, for a program writing the file itself (like the bot)
+    /// to keep first_top from reading or rewriting it meanwhile.
+    ///
+    /// The methods of the database take the lock themselves, and cannot be called on the collection
+    /// while it is held, even in the same process: they would wait for it until the lock timeout.
+    pub fn lock_exclusive(&self, collection: &str) -> Result<Lock> {
+        Ok(Lock {
+            _file: self.lock(collection, true)?,
+        })
+    }
+
+    fn file_path(&self, collection: &str) -> PathBuf {
+        Path::new(&self.path).join(format!("{}.{}", collection, self.extension))
+    }
+
+    fn index_path(&self, collection: &str) -> PathBuf {
+        Path::new(&self.path).join(format!(".{}.{}.index", collection, self.extension))
+    }
+
+    /// Bring the index of a collection of results up to date, reading the rows appended since it was written,
+    /// or all of them when there is none or the collection was rewritten since.
+    ///
+    /// The index is written back when it changed, unless the directory is read-only.
+    fn index(&self, collection: &str) -> Result<Index> {
+        let path = self.file_path(collection);
+        let index_path = self.index_path(collection);
+        let io_error = |source| Error::Io {
+            path: path.clone(),
+            source,
+        };
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Index::default()),
+            Err(error) => return Err(io_error(error)),
+        };
+        let mut index = match Index::read(&index_path) {
+            Some(index) if index.applies_to(&mut file).map_err(io_error)? => index,
+            _ => Index::default(),
+        };
+        let unindexed = index.unindexed();
+        let mut select = self.open(
+            collection,
+            |_: &FirstResult| true,
+            None,
+            unindexed.map(|span| vec![span]),
+        )?;
+        let mut read = false;
+        // Each row is indexed once the next one is read, as the last may still be being written.
+        let mut pending = None;
+
+        while let Some((result, start)) = select.next_row() {
+            if let Some((result, start)) = pending.replace((result.ok(), start)) {
+                index.add(start, result.as_ref());
+            }
+            read = true;
+        }
+
+        if read || unindexed.is_none() {
+            let (mut len, mut lines) = select.end();
+
+            if let Some((result, start)) = pending {
+                if ends_line(&mut file, len).map_err(io_error)? {
+                    index.add(start, result.as_ref());
+                } else {
+                    len = start.offset;
+                    lines = start.line - 1;
+                }
+            }
+
+            index.end(len, lines, &mut file).map_err(io_error)?;
+            let _ = index.write(&index_path);
+        }
+
+        Ok(index)
+    }
+
+    /// Select the results of a channel played within range, reading only the days of the range
+    /// that the channel played on, as the index of the collection tells.
+    fn read_range(
+        &self,
+        from: &str,
+        channel: Option<&str>,
+        range: &DateRange,
+        strict: bool,
+    ) -> Result<(Option<Vec<FirstResult>>, Vec<Error>)> {
+        let lock = self.lock(from, false)?;
+        let spans = self.index(from)?.spans(channel, range);
+
+        collect(
+            self.open(from, in_range(channel, range), lock, spans)?,
+            strict,
+        )
+    }
+
+    /// Iterate over the entities matching where_filter while reading the collection.
+    ///
+    /// A collection that does not exist yields no entities.
+    pub fn select_iter<T, P>(&self, from: &str, where_filter: P) -> Result<SelectIter<T, P>>
+    where
+        T: CsvRecord,
+        P: FnMut(&T) -> bool,
+    {
+        let lock = self.lock(from, false)?;
+
+        self.open(from, where_filter, lock, None)
+    }
+
+    /// Open a collection to read its rows, or only those of the given parts of it.
+    fn open<T, P>(
         &self,
         from: &str,
         where_filter: P,
-    ) -> Result<Option<Vec<T>>, Box<dyn Error>>
+        lock: Option<File>,
+        spans: Option<Vec<Span>>,
+    ) -> Result<SelectIter<T, P>>
     where
         T: CsvRecord,
         P: FnMut(&T) -> bool,
     {
-        let mut entities: Vec<T> = Vec::new();
-        let mut rdr = match csv::ReaderBuilder::new()
-            .has_headers(false)
-            .from_path(format!("{}{}.{}", self.path, from, self.extension))
+        open_file(self.file_path(from), where_filter, lock, spans)
+    }
+
+    /// Whether rows of T can be appended to a collection as they are:
+    /// it is empty or written in the layout write would use.
+    fn appendable<T: CsvRecord>(&self, path: &Path) -> io::Result<bool> {
+        let mut reader = match File::open(path) {
+            Ok(file) => BufReader::new(file),
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(true),
+            Err(error) => return Err(error),
+        };
+        let (version, first_line) = read_marker(&mut reader)?;
+
+        if first_line.is_empty() {
+            return Ok(true);
+        }
+
+        if !self.versioned::<T>() {
+            return Ok(version.is_none());
+        }
+
+        let mut headers = String::new();
+
+        reader.read_line(&mut headers)?;
+
+        let headers = headers.trim_end_matches(['\r', '\n']);
+
+        Ok(version == Some(T::VERSION) && headers == T::HEADERS.join(","))
+    }
+
+    /// Whether collections of T are written with the schema marker and headers.
+    fn versioned<T: CsvRecord>(&self) -> bool {
+        self.headers || T::VERSION > 1
+    }
+
+    fn read<T, P>(
+        &self,
+        from: &str,
+        where_filter: P,
+        strict: bool,
+    ) -> Result<(Option<Vec<T>>, Vec<Error>)>
+    where
+        T: CsvRecord,
+        P: FnMut(&T) -> bool,
+    {
+        collect(self.select_iter(from, where_filter)?, strict)
+    }
+
+    /// Rewrite a collection whose exclusive lock is held, migrating it to the current layout.
+    fn change<T, F>(&self, collection: &str, change: F) -> Result<()>
+    where
+        T: CsvRecord,
+        F: FnOnce(Vec<T>) -> Result<Vec<T>>,
+    {
+        let (entities, _warnings) = collect(self.open(collection, |_| true, None, None)?, true)?;
+        let entities = change(entities.unwrap_or_default())?;
+
+        self.replace(collection, &entities.iter().collect::<Vec<_>>())
+    }
+
+    fn replace<T: CsvRecord>(&self, to: &str, entities: &[&T]) -> Result<()> {
+        let path = self.file_path(to);
+
+        // The rows move, and are indexed again on the next range query.
+        match fs::remove_file(self.index_path(to)) {
+            Err(error) if error.kind() != ErrorKind::NotFound => {
+                return Err(Error::Io {
+                    path: self.index_path(to),
+                    source: error,
+                });
+            }
+            _ => {}
+        }
+
+        let temp = path.with_file_name(format!(
+            ".{}.{}.{}.tmp",
+            to,
+            self.extension,
+            std::process::id()
+        ));
+
+        let result = self
+            .write_temp(&temp, &path, entities)
+            .and_then(|()| self.backup(&path))
+            .and_then(|()| {
+                fs::rename(&temp, &path).map_err(|source| Error::Io {
+                    path: path.clone(),
+                    source,
+                })
+            })
+            .and_then(|()| sync_dir(&path));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+
+        result
+    }
+
+    /// Take the lock of a collection, shared for reading or exclusive for writing, waiting up to lock_timeout.
+    ///
+    /// The lock is a separate file next to the collection, as writes replace the collection file itself.
+    /// It is released when the returned file is dropped. Readers that cannot create the lock file
+    /// (in a read-only directory) read without it.
+    fn lock(&self, collection: &str, exclusive: bool) -> Result<Option<File>> {
+        let path = Path::new(&self.path).join(format!(".{}.{}.lock", collection, self.extension));
+        let io_error = |source| Error::Io {
+            path: path.clone(),
+            source,
+        };
+        let file = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
         {
-            Ok(rdr) => rdr,
-            Err(error) => match error.kind() {
-                csv::ErrorKind::Io(error) => match error.kind() {
-                    ErrorKind::NotFound => return Ok(None),
-                    ErrorKind::PermissionDenied => {
-                        return Err(Box::new(CsvDbError(String::from("Permission denied"))));
-                    }
-                    _ => return Err(Box::new(CsvDbError(String::from("Problem reading file")))),
-                },
-                _ => return Err(Box::new(CsvDbError(String::from("Unknown problem")))),
-            },
+            Ok(file) => file,
+            Err(error)
+                if !exclusive
+                    && matches!(
+                        error.kind(),
+                        ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem
+                    ) =>
+            {
+                return Ok(None);
+            }
+            Err(error) => return Err(io_error(error)),
         };
+        let deadline = Instant::now() + self.lock_timeout;
 
-        for result in rdr.records() {
-            let record = result?;
-            let mut fields: Vec<String> = Vec::new();
+        loop {
+            let result = match exclusive {
+                true => file.try_lock(),
+                false => file.try_lock_shared(),
+            };
 
-            for field in &record {
-                fields.push(String::from(field));
+            match result {
+                Ok(()) => return Ok(Some(file)),
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    thread::sleep(LOCK_RETRY)
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(Error::Locked {
+                        path: self.file_path(collection),
+                        timeout: self.lock_timeout,
+                    });
+                }
+                Err(TryLockError::Error(error)) => return Err(io_error(error)),
             }
+        }
+    }
+
+    fn write_temp<T: CsvRecord>(&self, temp: &Path, path: &Path, entities: &[&T]) -> Result<()> {
+        let io_error = |source| Error::Io {
+            path: temp.to_path_buf(),
+            source,
+        };
+        let file = File::create(temp).map_err(io_error)?;
+
+        // Keep the permissions of the collection being replaced.
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())
+                .map_err(io_error)?;
+        }
+
+        write_rows(temp, file, self.versioned::<T>(), entities)?
+            .sync_all()
+            .map_err(io_error)
+    }
+
+    /// Rotate the backups of a collection and make its current version the latest backup.
+    fn backup(&self, path: &Path) -> Result<()> {
+        if self.backups == 0 || !path.exists() {
+            return Ok(());
+        }
+
+        let backup = |number: usize| {
+            let mut name = path.as_os_str().to_owned();
 
-            let entity = T::from_fields(&fields);
+            name.push(format!(".{number}"));
+            PathBuf::from(name)
+        };
+        let io_error = |path: PathBuf| move |source| Error::Io { path, source };
 
-            entities.push(entity);
+        for number in (1..self.backups).rev() {
+            if backup(number).exists() {
+                fs::rename(backup(number), backup(number + 1))
+                    .map_err(io_error(backup(number + 1)))?;
+            }
         }
 
-        entities = entities.into_iter().filter(where_filter).collect();
+        let latest = backup(1);
 
-        match entities.is_empty() {
-            true => Ok(None),
-            false => Ok(Some(entities)),
+        if latest.exists() {
+            fs::remove_file(&latest).map_err(io_error(latest.clone()))?;
         }
+
+        // A hard link keeps the collection in place until the new version is renamed over it.
+        fs::hard_link(path, &latest)
+            .or_else(|_| fs::copy(path, &latest).map(|_| ()))
+            .map_err(io_error(latest))
     }
+}
 
-    pub fn insert<T>(&self, into: &str, entity: T) -> Result<(), Box<dyn Error>>
+impl Storage for Database {
+    fn select<T, P>(&self, from: &str, where_filter: P) -> Result<Option<Vec<T>>>
     where
         T: CsvRecord,
+        P: FnMut(&T) -> bool,
     {
-        let mut entities: Vec<T> = self.select(into, |_| true)?.unwrap_or_default();
-
-        entities.push(entity);
-
-        self.write(into, &entities.iter().collect())?;
+        self.read(from, where_filter, true)
+            .map(|(entities, _warnings)| entities)
+    }
 
-        Ok(())
+    fn select_lenient<T, P>(
+        &self,
+        from: &str,
+        where_filter: P,
+    ) -> Result<(Option<Vec<T>>, Vec<Error>)>
+    where
+        T: CsvRecord,
+        P: FnMut(&T) -> bool,
+    {
+        self.read(from, where_filter, false)
     }
 
-    pub fn update<T, P>(&self, from: &str, entity: T, where_filter: P) -> Result<(), Box<dyn Error>>
+    /// Appends to the file, without rewriting it.
+    fn insert<T>(&self, into: &str, entity: T) -> Result<()>
     where
-        T: CsvRecord + PartialEq,
-        P: FnMut(&&T) -> bool,
+        T: CsvRecord,
     {
-        let entities = self.select(from, |_| true)?.unwrap_or_default();
-        let delete: Vec<&T> = entities.iter().filter(where_filter).collect();
-        let mut keep: Vec<&T> = Vec::new();
+        let _lock = self.lock(into, true)?;
+        let path = self.file_path(into);
+        let io_error = |source| Error::Io {
+            path: path.clone(),
+            source,
+        };
 
-        for entity in &entities {
-            if !delete.contains(&entity) {
-                keep.push(entity)
+        // Collections in another layout are migrated by rewriting them whole.
+        if !self.appendable::<T>(&path).map_err(io_error)? {
+            return self.change(into, |mut entities: Vec<T>| {
+                entities.push(entity);
+
+                Ok(entities)
+            });
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(io_error)?;
+        let empty = file.metadata().map_err(io_error)?.len() == 0;
+
+        // A last row without its line terminator would be merged with the new one.
+        if !empty {
+            let mut last = [0; 1];
+
+            file.seek(SeekFrom::End(-1)).map_err(io_error)?;
+            file.read_exact(&mut last).map_err(io_error)?;
+
+            if last[0] != b'\n' {
+                file.write_all(b"\n").map_err(io_error)?;
             }
         }
 
-        keep.push(&entity);
+        if empty {
+            preamble::<T>(&mut file, self.versioned::<T>()).map_err(io_error)?;
+        }
+
+        let mut wtr = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(file);
 
-        self.write(from, &keep)?;
+        wtr.write_record(entity.to_fields())
+            .map_err(|error| Error::csv(&path, error))?;
+        wtr.flush().map_err(io_error)?;
 
         Ok(())
     }
 
-    #[allow(dead_code)]
-    pub fn delete<T, P>(&self, from: &str, where_filter: P) -> Result<(), Box<dyn Error>>
+    /// Holds the exclusive lock of the collection throughout.
+    fn rewrite<T, F>(&self, collection: &str, change: F) -> Result<()>
     where
-        T: CsvRecord + PartialEq,
-        P: FnMut(&&T) -> bool,
+        T: CsvRecord,
+        F: FnOnce(Vec<T>) -> Result<Vec<T>>,
     {
-        let entities = self.select(from, |_| true)?.unwrap_or_default();
-        let delete: Vec<&T> = entities.iter().filter(where_filter).collect();
-        let mut keep: Vec<&T> = Vec::new();
+        let _lock = self.lock(collection, true)?;
+
+        self.change(collection, change)
+    }
 
-        for entity in &entities {
-            if !delete.contains(&entity) {
-                keep.push(entity)
+    /// Only the days of the range that the channel played on are read, as the index of the collection tells.
+    ///
+    /// Invalid rows of other days are not read, and so do not make the selection fail.
+    fn select_range(
+        &self,
+        from: &str,
+        channel: Option<&str>,
+        range: &DateRange,
+    ) -> Result<Option<Vec<FirstResult>>> {
+        self.read_range(from, channel, range, true)
+            .map(|(entities, _warnings)| entities)
+    }
+
+    /// Only the invalid rows of the days read are returned.
+    fn select_range_lenient(
+        &self,
+        from: &str,
+        channel: Option<&str>,
+        range: &DateRange,
+    ) -> Result<(Option<Vec<FirstResult>>, Vec<Error>)> {
+        self.read_range(from, channel, range, false)
+    }
+
+    /// The rows are written to a temporary file in the same directory, synced to disk
+    /// and renamed over the collection, so a crash leaves either the old or the new version whole.
+    fn write<T: CsvRecord>(&self, to: &str, entities: &[&T]) -> Result<()> {
+        let _lock = self.lock(to, true)?;
+
+        self.replace(to, entities)
+    }
+}
+
+/// Read the entities of a CSV file matching where_filter, outside of any database:
+/// without taking a lock, nor reading or writing an index.
+///
+/// Invalid rows fail the read if strict, and are otherwise skipped and returned as warnings.
+pub fn read_file<T, P>(
+    path: &Path,
+    where_filter: P,
+    strict: bool,
+) -> Result<(Option<Vec<T>>, Vec<Error>)>
+where
+    T: CsvRecord,
+    P: FnMut(&T) -> bool,
+{
+    let select = open_file(path.to_path_buf(), where_filter, None, None)?;
+
+    // Unlike collections, files that do not exist are not empty.
+    if select.file.is_none() {
+        return Err(Error::Io {
+            path: path.to_path_buf(),
+            source: io::Error::from(ErrorKind::NotFound),
+        });
+    }
+
+    collect(select, strict)
+}
+
+/// Write entities to a CSV file, replacing it, outside of any database:
+/// without taking a lock, nor keeping a backup.
+///
+/// The file has the layout of the bot (headerless) for records of version 1.
+pub fn write_file<T: CsvRecord>(path: &Path, entities: &[&T]) -> Result<()> {
+    let file = File::create(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    write_rows(path, file, T::VERSION > 1, entities)?
+        .sync_all()
+        .map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// Open a CSV file to read its rows, or only those of the given parts of it.
+fn open_file<T, P>(
+    path: PathBuf,
+    where_filter: P,
+    lock: Option<File>,
+    spans: Option<Vec<Span>>,
+) -> Result<SelectIter<T, P>>
+where
+    T: CsvRecord,
+    P: FnMut(&T) -> bool,
+{
+    let mut select = SelectIter {
+        path,
+        file: None,
+        records: None,
+        span: Span {
+            start: 0,
+            end: None,
+            line: 1,
+        },
+        spans: Vec::new(),
+        version: 1,
+        columns: None,
+        where_filter,
+        entity: PhantomData,
+        _lock: lock,
+    };
+    let io_error = |source| Error::Io {
+        path: select.path.clone(),
+        source,
+    };
+    let mut file = match File::open(&select.path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(select),
+        Err(error) => return Err(io_error(error)),
+    };
+    let (version, first_line) = read_marker(&mut BufReader::new(&mut file)).map_err(io_error)?;
+
+    if let Some(version) = version {
+        if version > T::VERSION {
+            return Err(Error::Record {
+                path: select.path,
+                line: 1,
+                message: format!(
+                    "schema version {version} is newer than the supported version {}",
+                    T::VERSION
+                ),
+            });
+        }
+
+        select.version = version;
+        // The rows start after the marker, with the line of headers.
+        select.span = Span {
+            start: first_line.len() as u64,
+            end: None,
+            line: 2,
+        };
+    }
+
+    file.seek(SeekFrom::Start(select.span.start))
+        .map_err(io_error)?;
+
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(version.is_some())
+        .flexible(true)
+        .from_reader(file.try_clone().map_err(io_error)?.take(u64::MAX));
+
+    if version.is_some() && !T::HEADERS.is_empty() {
+        let headers = rdr
+            .headers()
+            .map_err(|error| Error::csv(&select.path, error))?;
+
+        select.columns = Some(
+            T::HEADERS
+                .iter()
+                .map(|name| headers.iter().position(|header| header == *name))
+                .collect(),
+        );
+    }
+
+    select.file = Some(file);
+
+    match spans {
+        Some(mut spans) => {
+            spans.reverse();
+            select.spans = spans;
+            select.records = None;
+        }
+        None => select.records = Some(rdr.into_records()),
+    }
+
+    Ok(select)
+}
+
+/// Write the schema marker and the headers that start a versioned collection.
+fn preamble<T: CsvRecord>(out: &mut impl Write, versioned: bool) -> io::Result<()> {
+    match versioned {
+        true => write!(
+            out,
+            "{SCHEMA_MARKER}{}\n{}\n",
+            T::VERSION,
+            T::HEADERS.join(",")
+        ),
+        false => Ok(()),
+    }
+}
+
+/// Write the rows of entities to the file at path, after the preamble if versioned,
+/// returning the file once they are flushed.
+fn write_rows<T: CsvRecord>(
+    path: &Path,
+    mut file: File,
+    versioned: bool,
+    entities: &[&T],
+) -> Result<File> {
+    let io_error = |source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    preamble::<T>(&mut file, versioned).map_err(io_error)?;
+
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(file);
+
+    for entity in entities {
+        let fields = entity.to_fields();
+
+        wtr.write_record(fields)
+            .map_err(|error| Error::csv(path, error))?;
+    }
+
+    wtr.into_inner()
+        .map_err(|error| io_error(error.into_error()))
+}
+
+/// Sync the directory of a file, so a rename in it survives a crash.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .map_err(|source| Error::Io {
+            path: dir.to_path_buf(),
+            source,
+        })
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Read the schema marker of a collection, if its first line is one.
+///
+/// Returns the version of the marker, if any, and the first line read.
+fn read_marker(reader: &mut impl BufRead) -> io::Result<(Option<u32>, Vec<u8>)> {
+    let mut first_line = Vec::new();
+
+    reader.read_until(b'\n', &mut first_line)?;
+
+    let version = std::str::from_utf8(&first_line)
+        .ok()
+        .and_then(|line| line.trim_end().strip_prefix(SCHEMA_MARKER))
+        .and_then(|version| version.parse().ok());
+
+    Ok((version, first_line))
+}
+
+/// Whether the part of a file up to len ends with a complete line.
+fn ends_line(file: &mut File, len: u64) -> io::Result<bool> {
+    let Some(last) = len.checked_sub(1) else {
+        return Ok(true);
+    };
+    let mut byte = [0];
+
+    file.seek(SeekFrom::Start(last))?;
+    file.read_exact(&mut byte)?;
+
+    Ok(byte[0] == b'\n')
+}
+
+/// Collect the entities of a selection, failing on invalid rows if strict and otherwise returning them as warnings.
+fn collect<T, P>(select: SelectIter<T, P>, strict: bool) -> Result<(Option<Vec<T>>, Vec<Error>)>
+where
+    T: CsvRecord,
+    P: FnMut(&T) -> bool,
+{
+    let mut entities: Vec<T> = Vec::new();
+    let mut warnings: Vec<Error> = Vec::new();
+
+    for entity in select {
+        match entity {
+            Ok(entity) => entities.push(entity),
+            // Rows that cannot be read are skipped in lenient mode, unlike I/O failures.
+            Err(error @ (Error::Record { .. } | Error::Csv { .. })) if !strict => {
+                warnings.push(error)
             }
+            Err(error) => return Err(error),
         }
+    }
 
-        self.write(from, &keep)?;
+    match entities.is_empty() {
+        true => Ok((None, warnings)),
+        false => Ok((Some(entities), warnings)),
+    }
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::ops::Bound;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process;
+
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::FirstResult;
+    use crate::test_support::TempDir;
+
+    fn database(dir: &TempDir) -> Database {
+        Database::new(dir.path.to_str().unwrap(), None)
     }
 
-    pub fn write<T: CsvRecord>(&self, to: &str, entities: &Vec<&T>) -> Result<(), Box<dyn Error>> {
-        let mut wtr = match csv::WriterBuilder::new()
-            .has_headers(false)
-            .from_path(format!("{}{}.{}", self.path, to, self.extension))
-        {
-            Ok(wtr) => wtr,
-            Err(error) => match error.kind() {
-                csv::ErrorKind::Io(_) => {
-                    match File::create(format!("{}{}.{}", self.path, to, self.extension)) {
-                        Ok(_) => csv::WriterBuilder::new()
-                            .has_headers(false)
-                            .from_path(format!("{}{}.{}", self.path, to, self.extension))?,
-                        Err(_) => {
-                            eprintln!("Could not create database file.");
-
-                            return Err(Box::new(error));
-                        }
-                    }
-                }
-                _ => {
-                    eprintln!("Could not create database file.");
+    fn result(nick: &str, day: u32) -> FirstResult {
+        FirstResult {
+            nick: String::from(nick),
+            channel: String::from("#test"),
+            datetime: Utc.with_ymd_and_hms(2025, 3, day, 9, 0, 0).unwrap(),
+            timezone: String::from("Europe/Paris"),
+        }
+    }
 
-                    return Err(Box::new(error));
-                }
-            },
-        };
+    /// A record at version 2, which added the timezone to the nick and points of version 1.
+    #[derive(Debug, PartialEq)]
+    struct Score {
+        nick: String,
+        points: u32,
+        timezone: String,
+    }
 
-        for entity in entities {
-            let fields = entity.to_fields();
+    impl CsvRecord for Score {
+        const HEADERS: &'static [&'static str] = &["nick", "points", "timezone"];
+        const VERSION: u32 = 2;
 
-            wtr.write_record(fields)?;
+        fn from_fields(fields: &[String]) -> std::result::Result<Self, String> {
+            let [nick, points, timezone] = fields else {
+                return Err(format!("expected 3 fields, found {}", fields.len()));
+            };
+
+            Ok(Self {
+                nick: nick.clone(),
+                points: points
+                    .parse()
+                    .map_err(|_| format!("invalid points {points}"))?,
+                timezone: timezone.clone(),
+            })
         }
 
-        Ok(())
+        fn to_fields(&self) -> Vec<String> {
+            vec![
+                self.nick.clone(),
+                self.points.to_string(),
+                self.timezone.clone(),
+            ]
+        }
+
+        fn migrate(
+            version: u32,
+            mut fields: Vec<String>,
+        ) -> std::result::Result<Vec<String>, String> {
+            if version == 1 {
+                fields.push(String::from("UTC"));
+            }
+
+            Ok(fields)
+        }
+    }
+
+    fn score(nick: &str, points: u32, timezone: &str) -> Score {
+        Score {
+            nick: String::from(nick),
+            points,
+            timezone: String::from(timezone),
+        }
+    }
+
+    fn row(nick: &str, day: u32) -> String {
+        format!("{nick},#test,2025-03-{day:02} 09:00:00 UTC,Europe/Paris\n")
+    }
+
+    #[test]
+    fn write_replaces_the_collection_without_leaving_a_temporary_file() {
+        let dir = TempDir::new("write");
+        let db = database(&dir);
+
+        db.write("results", &[&result("alice", 1)]).unwrap();
+        db.write("results", &[&result("bob", 2), &result("carol", 3)])
+            .unwrap();
+
+        assert_eq!(dir.read("results.csv"), row("bob", 2) + &row("carol", 3));
+        assert_eq!(dir.files(), [".results.csv.lock", "results.csv"]);
+    }
+
+    #[test]
+    fn backups_are_rotated() {
+        let dir = TempDir::new("backups");
+        let db = database(&dir).with_backups(2);
+
+        db.write("results", &[&result("alice", 1)]).unwrap();
+        db.write("results", &[&result("bob", 2)]).unwrap();
+        db.write("results", &[&result("carol", 3)]).unwrap();
+
+        assert_eq!(dir.read("results.csv"), row("carol", 3));
+        assert_eq!(dir.read("results.csv.1"), row("bob", 2));
+        assert_eq!(dir.read("results.csv.2"), row("alice", 1));
+
+        db.write("results", &[&result("dave", 4)]).unwrap();
+
+        assert_eq!(dir.read("results.csv.1"), row("carol", 3));
+        assert_eq!(dir.read("results.csv.2"), row("bob", 2));
+        assert!(!dir.join("results.csv.3").exists());
+    }
+
+    #[test]
+    fn failed_writes_keep_the_collection() {
+        let dir = TempDir::new("failed-write");
+        let db = database(&dir);
+
+        db.write("results", &[&result("alice", 1)]).unwrap();
+
+        // A directory in the way of the temporary file.
+        let temp = dir.join(&format!(".results.csv.{}.tmp", process::id()));
+        fs::create_dir(&temp).unwrap();
+
+        assert!(matches!(
+            db.write("results", &[&result("bob", 2)]),
+            Err(Error::Io { .. })
+        ));
+        assert_eq!(dir.read("results.csv"), row("alice", 1));
+
+        fs::remove_dir(&temp).unwrap();
+        fs::set_permissions(&dir.path, fs::Permissions::from_mode(0o555)).unwrap();
+
+        // Permissions do not apply to root, which can still write in the directory.
+        if File::create(dir.join("probe")).is_ok() {
+            return;
+        }
+
+        assert!(matches!(
+            db.write("results", &[&result("bob", 2)]),
+            Err(Error::Io { .. })
+        ));
+        assert_eq!(dir.read("results.csv"), row("alice", 1));
+        assert_eq!(dir.files(), [".results.csv.lock", "results.csv"]);
+    }
+
+    #[test]
+    fn headerless_collections_are_migrated_from_version_1() {
+        let dir = TempDir::new("migrate");
+
+        fs::write(dir.join("scores.csv"), "alice,3\nbob,2\n").unwrap();
+
+        assert_eq!(
+            database(&dir).select("scores", |_: &Score| true).unwrap(),
+            Some(vec![score("alice", 3, "UTC"), score("bob", 2, "UTC")])
+        );
+    }
+
+    #[test]
+    fn headers_map_the_columns_in_any_order() {
+        let dir = TempDir::new("headers");
+
+        fs::write(
+            dir.join("scores.csv"),
+            "#schema 2\ntimezone,nick,points\nEurope/Paris,alice,3\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            database(&dir).select("scores", |_: &Score| true).unwrap(),
+            Some(vec![score("alice", 3, "Europe/Paris")])
+        );
+    }
+
+    #[test]
+    fn newer_schema_versions_are_rejected() {
+        let dir = TempDir::new("newer");
+
+        fs::write(
+            dir.join("scores.csv"),
+            "#schema 3\nnick,points,timezone,team\nalice,3,UTC,red\n",
+        )
+        .unwrap();
+
+        assert!(matches!(
+            database(&dir).select("scores", |_: &Score| true),
+            Err(Error::Record { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn inserts_into_headerless_collections_add_the_headers() {
+        let dir = TempDir::new("insert-headers");
+        let db = database(&dir).with_headers(true);
+
+        fs::write(dir.join("results.csv"), row("alice", 1)).unwrap();
+        db.insert("results", result("bob", 2)).unwrap();
+
+        assert_eq!(
+            dir.read("results.csv"),
+            String::from("#schema 1\nnick,channel,datetime,timezone\n")
+                + &row("alice", 1)
+                + &row("bob", 2)
+        );
+    }
+
+    #[test]
+    fn inserts_append_after_a_last_row_missing_its_line_terminator() {
+        let dir = TempDir::new("insert-append");
+        let db = database(&dir);
+        // An invalid row, which a rewrite would fail on, shows that the file is only appended to.
+        let rows = row("alice", 1) + "carol,#test,yesterday,Europe/Paris";
+
+        fs::write(dir.join("results.csv"), &rows).unwrap();
+        db.insert("results", result("bob", 2)).unwrap();
+
+        assert_eq!(dir.read("results.csv"), rows + "\n" + &row("bob", 2));
+
+        db.insert("results", result("dave", 4)).unwrap();
+
+        assert!(
+            dir.read("results.csv")
+                .ends_with(&(row("bob", 2) + &row("dave", 4)))
+        );
+    }
+
+    #[test]
+    fn select_iter_reads_rows_as_it_is_iterated() {
+        let dir = TempDir::new("select-iter");
+        let db = database(&dir);
+
+        fs::write(
+            dir.join("results.csv"),
+            row("alice", 1)
+                + &row("bob", 2)
+                + &row("carol", 3)
+                + "dave,#test,yesterday,Europe/Paris\n"
+                + &row("erin", 5),
+        )
+        .unwrap();
+
+        let filtered = Cell::new(0);
+        let mut select = db
+            .select_iter("results", |result: &FirstResult| {
+                filtered.set(filtered.get() + 1);
+                result.nick == "bob"
+            })
+            .unwrap();
+
+        // The invalid row after bob is not read yet.
+        assert_eq!(select.next().unwrap().unwrap(), result("bob", 2));
+        assert_eq!(filtered.get(), 2);
+
+        assert!(matches!(
+            select.next(),
+            Some(Err(Error::Record { line: 4, .. }))
+        ));
+        assert_eq!(filtered.get(), 3);
+    }
+
+    #[test]
+    fn exclusive_locks_keep_writers_out_until_dropped() {
+        let dir = TempDir::new("lock");
+        let db = database(&dir).with_lock_timeout(Duration::ZERO);
+        let lock = db.lock_exclusive("results").unwrap();
+
+        assert!(matches!(
+            db.insert("results", result("alice", 1)),
+            Err(Error::Locked { .. })
+        ));
+
+        drop(lock);
+        db.insert("results", result("alice", 1)).unwrap();
+
+        assert_eq!(dir.read("results.csv"), row("alice", 1));
+    }
+
+    #[test]
+    fn range_selections_follow_appends_and_rewrites() {
+        let dir = TempDir::new("range");
+        let db = database(&dir);
+        let march = |from: u32, to: u32| DateRange {
+            start: Bound::Included(Utc.with_ymd_and_hms(2025, 3, from, 0, 0, 0).unwrap()),
+            end: Bound::Excluded(Utc.with_ymd_and_hms(2025, 3, to, 0, 0, 0).unwrap()),
+        };
+
+        db.write("results", &[&result("alice", 1), &result("bob", 2)])
+            .unwrap();
+
+        assert_eq!(
+            db.select_range("results", Some("#TEST"), &march(2, 3))
+                .unwrap(),
+            Some(vec![result("bob", 2)])
+        );
+
+        // Appended by the bot, after the index was written.
+        fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join("results.csv"))
+            .unwrap()
+            .write_all(row("carol", 3).as_bytes())
+            .unwrap();
+
+        assert_eq!(
+            db.select_range("results", None, &march(2, 4)).unwrap(),
+            Some(vec![result("bob", 2), result("carol", 3)])
+        );
+
+        db.write("results", &[&result("dave", 2)]).unwrap();
+
+        assert_eq!(
+            db.select_range("results", None, &march(1, 4)).unwrap(),
+            Some(vec![result("dave", 2)])
+        );
+        assert_eq!(
+            db.select_range("results", Some("#other"), &march(1, 4))
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn rows_being_appended_are_left_out_of_the_index() {
+        let dir = TempDir::new("range-partial");
+        let db = database(&dir);
+        let all = DateRange::default();
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join("results.csv"))
+            .unwrap();
+        let carol = row("carol", 3);
+        let (start, rest) = carol.split_at(10);
+
+        file.write_all((row("alice", 1) + start).as_bytes())
+            .unwrap();
+        let _ = db.select_range_lenient("results", None, &all);
+        file.write_all(rest.as_bytes()).unwrap();
+
+        assert_eq!(
+            db.select_range("results", None, &all).unwrap(),
+            Some(vec![result("alice", 1), result("carol", 3)])
+        );
+    }
+
+    #[test]
+    fn stale_indexes_are_rebuilt() {
+        let dir = TempDir::new("range-stale");
+        let db = database(&dir);
+
+        db.write("results", &[&result("alice", 1), &result("bob", 2)])
+            .unwrap();
+        fs::write(
+            dir.join(".results.csv.index"),
+            "#index 1\n10,2,20,2,,ordered\n2025-03-02,5,1,#test\n2025-03-01,0,2,#test\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            db.select_range("results", None, &DateRange::default())
+                .unwrap(),
+            Some(vec![result("alice", 1), result("bob", 2)])
+        );
+    }
+
+    #[test]
+    fn strict_selects_fail_at_the_line_of_the_first_invalid_row() {
+        let dir = TempDir::new("strict");
+        let db = database(&dir);
+
+        fs::write(
+            dir.join("results.csv"),
+            row("alice", 1)
+                + &row("bob", 2)
+                + "carol,#test,yesterday,Europe/Paris\n"
+                + &row("dave", 4),
+        )
+        .unwrap();
+
+        let error = db.select("results", |_: &FirstResult| true).unwrap_err();
+
+        assert!(
+            matches!(&error, Error::Record { line: 3, message, .. } if message == "invalid timestamp yesterday"),
+            "{error:?}"
+        );
+        assert!(matches!(
+            db.select_range("results", None, &DateRange::default()),
+            Err(Error::Record { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn lenient_selects_skip_invalid_rows_and_report_their_lines() {
+        let dir = TempDir::new("lenient");
+        let db = database(&dir);
+
+        fs::write(
+            dir.join("results.csv"),
+            String::from("#schema 1\nnick,channel,datetime,timezone\n")
+                + &row("alice", 1)
+                + "bob,#test,2025-03-02 09:00:00 UTC,Mars/Olympus\n"
+                + &row("carol", 3)
+                + "dave,#test\n",
+        )
+        .unwrap();
+
+        let (results, warnings) = db
+            .select_lenient("results", |_: &FirstResult| true)
+            .unwrap();
+        let lines = |warnings: &[Error]| -> Vec<u64> {
+            warnings
+                .iter()
+                .map(|warning| match warning {
+                    Error::Record { line, .. } => *line,
+                    other => panic!("expected a row error, got {other:?}"),
+                })
+                .collect()
+        };
+
+        assert_eq!(results, Some(vec![result("alice", 1), result("carol", 3)]));
+        assert_eq!(lines(&warnings), [4, 6]);
+
+        let (results, warnings) = db
+            .select_range_lenient("results", Some("#test"), &DateRange::default())
+            .unwrap();
+
+        assert_eq!(results, Some(vec![result("alice", 1), result("carol", 3)]));
+        assert_eq!(lines(&warnings), [4, 6]);
     }
 }
@@ -0,0 +1,494 @@
+//! Date expressions of queries (2025-03-14, last month, 3 weeks ago) and the spans of time they denote.
+
+use std::ops::Bound;
+
+use chrono::{
+    DateTime, Datelike, Days, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+
+use crate::Period;
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// The span of time a date expression denotes, from start (inclusive) to end (exclusive).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    /// First instant of the span.
+    pub start: DateTime<Utc>,
+    /// First instant after the span.
+    pub end: DateTime<Utc>,
+}
+
+/// Bounds of the results to select.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateRange {
+    /// Earliest time of the results.
+    pub start: Bound<DateTime<Utc>>,
+    /// Latest time of the results.
+    pub end: Bound<DateTime<Utc>>,
+}
+
+impl Default for DateRange {
+    fn default() -> Self {
+        Self {
+            start: Bound::Unbounded,
+            end: Bound::Unbounded,
+        }
+    }
+}
+
+impl DateRange {
+    /// Whether a datetime is within the bounds.
+    pub fn contains(&self, datetime: &DateTime<Utc>) -> bool {
+        let after_start = match &self.start {
+            Bound::Included(start) => datetime >= start,
+            Bound::Excluded(start) => datetime > start,
+            Bound::Unbounded => true,
+        };
+        let before_end = match &self.end {
+            Bound::Included(end) => datetime <= end,
+            Bound::Excluded(end) => datetime < end,
+            Bound::Unbounded => true,
+        };
+
+        after_start && before_end
+    }
+}
+
+/// Parse a date expression into the span of time it denotes, relative to now, with days starting in tz.
+///
+/// Accepted expressions (case insensitive):
+/// - ISO dates, months and years: 2025-03-14, 2025-03, 2025.
+/// - Named months, optionally with a year: march 2025, mar 2025, march (the most recent one).
+/// - Relative days: now, today, yesterday, 3 days ago, 2 weeks ago, 1 month ago, 1 year ago.
+/// - Calendar periods: this/last week, month, year or season (a season is a calendar year).
+pub fn parse_span(expression: &str, now: DateTime<Utc>, tz: Tz) -> Result<Span, String> {
+    let expression = expression.trim().to_lowercase();
+    let words: Vec<&str> = expression.split_whitespace().collect();
+    let today = now.with_timezone(&tz).date_naive();
+    let invalid = || format!("Invalid date {expression}");
+
+    match words.as_slice() {
+        ["now"] => Ok(Span {
+            start: now,
+            end: now,
+        }),
+        ["today"] => day_span(today, tz),
+        ["yesterday"] => day_span(
+            today.checked_sub_days(Days::new(1)).ok_or_else(invalid)?,
+            tz,
+        ),
+        [count, unit, "ago"] => {
+            let count: u32 = count.parse().map_err(|_| invalid())?;
+            let day = match unit.trim_end_matches('s') {
+                "day" => today.checked_sub_days(Days::new(count as u64)),
+                "week" => today.checked_sub_days(Days::new(count as u64 * 7)),
+                "month" => today.checked_sub_months(Months::new(count)),
+                "year" => count
+                    .checked_mul(12)
+                    .and_then(|months| today.checked_sub_months(Months::new(months))),
+                _ => None,
+            };
+
+            day_span(day.ok_or_else(invalid)?, tz)
+        }
+        [which @ ("this" | "last"), unit] => {
+            let back = match *which {
+                "last" => 1,
+                _ => 0,
+            };
+            let start = match *unit {
+                "week" => today
+                    .checked_sub_days(Days::new(today.weekday().num_days_from_monday() as u64))
+                    .and_then(|monday| monday.checked_sub_days(Days::new(back * 7))),
+                "month" => today
+                    .with_day(1)
+                    .and_then(|first| first.checked_sub_months(Months::new(back as u32))),
+                "year" | "season" => NaiveDate::from_ymd_opt(today.year() - back as i32, 1, 1),
+                _ => None,
+            }
+            .ok_or_else(invalid)?;
+
+            match *unit {
+                "week" => span(start, start.checked_add_days(Days::new(7)), tz),
+                "month" => month_span(start, tz),
+                _ => year_span(start.year(), tz),
+            }
+        }
+        [month] if month_number(month).is_some() => {
+            let month = month_number(month).ok_or_else(invalid)?;
+            let year = match month <= today.month() {
+                true => today.year(),
+                false => today.year() - 1,
+            };
+
+            month_span(
+                NaiveDate::from_ymd_opt(year, month, 1).ok_or_else(invalid)?,
+                tz,
+            )
+        }
+        [month, year] if month_number(month).is_some() => {
+            let month = month_number(month).ok_or_else(invalid)?;
+            let year = year.parse().map_err(|_| invalid())?;
+
+            month_span(
+                NaiveDate::from_ymd_opt(year, month, 1).ok_or_else(invalid)?,
+                tz,
+            )
+        }
+        [iso] => {
+            let parts: Vec<&str> = iso.split('-').collect();
+
+            match parts.as_slice() {
+                [year] => year_span(year.parse().map_err(|_| invalid())?, tz),
+                [year, month] => month_span(
+                    NaiveDate::from_ymd_opt(
+                        year.parse().map_err(|_| invalid())?,
+                        month.parse().map_err(|_| invalid())?,
+                        1,
+                    )
+                    .ok_or_else(invalid)?,
+                    tz,
+                ),
+                _ => day_span(
+                    NaiveDate::parse_from_str(iso, "%Y-%m-%d").map_err(|_| invalid())?,
+                    tz,
+                ),
+            }
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// The month number (1 to 12) of a full or abbreviated (three letters or more) month name.
+fn month_number(name: &str) -> Option<u32> {
+    if name.len() < 3 {
+        return None;
+    }
+
+    MONTHS
+        .iter()
+        .position(|month| month.starts_with(name))
+        .map(|index| index as u32 + 1)
+}
+
+fn day_span(day: NaiveDate, tz: Tz) -> Result<Span, String> {
+    span(day, day.checked_add_days(Days::new(1)), tz)
+}
+
+fn month_span(first: NaiveDate, tz: Tz) -> Result<Span, String> {
+    span(first, first.checked_add_months(Months::new(1)), tz)
+}
+
+fn year_span(year: i32, tz: Tz) -> Result<Span, String> {
+    let first = NaiveDate::from_ymd_opt(year, 1, 1).ok_or(format!("Invalid year {year}"))?;
+
+    span(first, first.checked_add_months(Months::new(12)), tz)
+}
+
+fn span(first: NaiveDate, next: Option<NaiveDate>, tz: Tz) -> Result<Span, String> {
+    let next = next.ok_or(format!("Date out of range after {first}"))?;
+
+    Ok(Span {
+        start: start_of_day(first, tz),
+        end: start_of_day(next, tz),
+    })
+}
+
+/// Compute the start date based on the period of time we want to go back in time from now.
+///
+/// Calendar periods (day, week, month, year) start at midnight in tz, while rolling periods
+/// (daily, weekly, monthly, yearly) go back a number of days at the same local time,
+/// resolved like the start of a day when the clocks skip it or repeat it.
+pub fn start_date(period: Period, now: DateTime<Utc>, tz: Tz) -> Option<DateTime<Utc>> {
+    let now = now.with_timezone(&tz).naive_local();
+    let today = now.date();
+    let days = match period {
+        Period::Daily => 1,
+        Period::Day => return Some(start_of_day(today, tz)),
+        Period::Month => return Some(start_of_day(today.with_day(1)?, tz)),
+        Period::Monthly => 30,
+        Period::Week => {
+            let monday =
+                today.checked_sub_days(Days::new(today.weekday().num_days_from_monday() as u64))?;
+
+            return Some(start_of_day(monday, tz));
+        }
+        Period::Weekly => 7,
+        Period::Year => return Some(start_of_day(today.with_ordinal(1)?, tz)),
+        Period::Yearly => 365,
+        Period::Unknown => return Some(DateTime::default()),
+    };
+
+    now.checked_sub_days(Days::new(days))
+        .map(|start| resolve_local(start, tz))
+}
+
+/// The instant a day starts in a timezone.
+///
+/// When a DST transition skips midnight the day starts at the first local time that exists,
+/// and when midnight happens twice the day starts at the earliest one.
+pub fn start_of_day(day: NaiveDate, tz: Tz) -> DateTime<Utc> {
+    resolve_local(day.and_time(NaiveTime::MIN), tz)
+}
+
+/// The instant of a local time in a timezone, the first local time after it that exists
+/// when a DST transition skips it, and the earliest one when it happens twice.
+fn resolve_local(local: NaiveDateTime, tz: Tz) -> DateTime<Utc> {
+    (0..=24 * 4)
+        .map(|quarter| local + TimeDelta::minutes(quarter * 15))
+        .find_map(|local| tz.from_local_datetime(&local).earliest())
+        .map(|start| start.with_timezone(&Utc))
+        .unwrap_or_else(|| local.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, 0, 0).unwrap()
+    }
+
+    fn span(start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Span, String> {
+        Ok(Span { start, end })
+    }
+
+    #[test]
+    fn months_and_years_roll_over() {
+        let now = utc(2025, 1, 15, 12);
+
+        assert_eq!(
+            parse_span("last month", now, Tz::UTC),
+            span(utc(2024, 12, 1, 0), utc(2025, 1, 1, 0))
+        );
+        assert_eq!(
+            parse_span("december 2024", now, Tz::UTC),
+            span(utc(2024, 12, 1, 0), utc(2025, 1, 1, 0))
+        );
+        assert_eq!(
+            parse_span("2024-12", now, Tz::UTC),
+            parse_span("dec 2024", now, Tz::UTC)
+        );
+        assert_eq!(
+            parse_span("march", now, Tz::UTC),
+            span(utc(2024, 3, 1, 0), utc(2024, 4, 1, 0))
+        );
+        assert_eq!(
+            parse_span("last week", now, Tz::UTC),
+            span(utc(2025, 1, 6, 0), utc(2025, 1, 13, 0))
+        );
+        assert_eq!(
+            parse_span("3 weeks ago", now, Tz::UTC),
+            span(utc(2024, 12, 25, 0), utc(2024, 12, 26, 0))
+        );
+        assert_eq!(
+            parse_span("last season", now, Tz::UTC),
+            span(utc(2024, 1, 1, 0), utc(2025, 1, 1, 0))
+        );
+    }
+
+    #[test]
+    fn months_ago_land_on_the_last_day_of_shorter_months() {
+        assert_eq!(
+            parse_span("1 month ago", utc(2025, 3, 31, 12), Tz::UTC),
+            span(utc(2025, 2, 28, 0), utc(2025, 3, 1, 0))
+        );
+        assert_eq!(
+            parse_span("1 year ago", utc(2024, 2, 29, 12), Tz::UTC),
+            span(utc(2023, 2, 28, 0), utc(2023, 3, 1, 0))
+        );
+    }
+
+    #[test]
+    fn out_of_range_dates_are_invalid() {
+        let now = utc(2025, 1, 15, 12);
+
+        assert!(parse_span("4000000000 years ago", now, Tz::UTC).is_err());
+        assert!(parse_span("2025-13", now, Tz::UTC).is_err());
+        assert!(parse_span("2025-02-30", now, Tz::UTC).is_err());
+        assert!(parse_span("999999", now, Tz::UTC).is_err());
+    }
+
+    #[test]
+    fn days_start_at_midnight_in_the_timezone() {
+        let now = utc(2025, 7, 1, 12);
+
+        assert_eq!(
+            parse_span("2025-07-01", now, Tz::Europe__Paris),
+            span(utc(2025, 6, 30, 22), utc(2025, 7, 1, 22))
+        );
+        assert_eq!(
+            parse_span("today", utc(2025, 6, 30, 23), Tz::Europe__Paris),
+            span(utc(2025, 6, 30, 22), utc(2025, 7, 1, 22))
+        );
+    }
+
+    #[test]
+    fn days_around_dst_changes_are_shorter_or_longer() {
+        let now = utc(2025, 12, 1, 12);
+        let hours = |expression: &str| {
+            let span = parse_span(expression, now, Tz::Europe__London).unwrap();
+
+            (span.end - span.start).num_hours()
+        };
+
+        assert_eq!(hours("2025-03-30"), 23);
+        assert_eq!(hours("2025-10-26"), 25);
+        assert_eq!(hours("2025-03"), 31 * 24 - 1);
+    }
+
+    #[test]
+    fn days_start_at_the_first_local_time_when_dst_skips_midnight() {
+        // São Paulo moved its clocks from midnight to 01:00 on 2018-11-04.
+        assert_eq!(
+            start_of_day(
+                NaiveDate::from_ymd_opt(2018, 11, 4).unwrap(),
+                Tz::America__Sao_Paulo
+            ),
+            utc(2018, 11, 4, 3)
+        );
+    }
+
+    #[test]
+    fn days_start_at_the_earliest_midnight_when_it_happens_twice() {
+        // Havana moves its clocks back from 01:00 to midnight on the first Sunday of November.
+        assert_eq!(
+            start_of_day(
+                NaiveDate::from_ymd_opt(2025, 11, 2).unwrap(),
+                Tz::America__Havana
+            ),
+            utc(2025, 11, 2, 4)
+        );
+    }
+
+    fn utc_at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn rolling_periods_keep_the_local_time_across_dst_changes() {
+        // 01:30 in London, a week after the clocks went forward and back.
+        assert_eq!(
+            start_date(
+                Period::Weekly,
+                utc_at(2025, 4, 2, 0, 30),
+                Tz::Europe__London
+            ),
+            Some(utc_at(2025, 3, 26, 1, 30))
+        );
+        assert_eq!(
+            start_date(
+                Period::Weekly,
+                utc_at(2025, 10, 29, 1, 30),
+                Tz::Europe__London
+            ),
+            Some(utc_at(2025, 10, 22, 0, 30))
+        );
+        assert_eq!(
+            start_date(
+                Period::Monthly,
+                utc_at(2025, 4, 15, 11, 0),
+                Tz::Europe__Paris
+            ),
+            Some(utc_at(2025, 3, 16, 12, 0))
+        );
+    }
+
+    #[test]
+    fn rolling_periods_landing_in_a_dst_gap_start_after_it() {
+        // 01:30 in London did not exist on 2025-03-30, the clocks going from 01:00 to 02:00.
+        assert_eq!(
+            start_date(
+                Period::Weekly,
+                utc_at(2025, 4, 6, 0, 30),
+                Tz::Europe__London
+            ),
+            Some(utc_at(2025, 3, 30, 1, 0))
+        );
+        assert_eq!(
+            start_date(
+                Period::Daily,
+                utc_at(2025, 3, 31, 0, 30),
+                Tz::Europe__London
+            ),
+            Some(utc_at(2025, 3, 30, 1, 0))
+        );
+    }
+
+    #[test]
+    fn rolling_periods_landing_in_a_repeated_hour_start_at_its_earliest() {
+        // 01:30 in London happened twice on 2025-10-26, the clocks going from 02:00 back to 01:00.
+        assert_eq!(
+            start_date(
+                Period::Weekly,
+                utc_at(2025, 11, 2, 1, 30),
+                Tz::Europe__London
+            ),
+            Some(utc_at(2025, 10, 26, 0, 30))
+        );
+        assert_eq!(
+            start_date(
+                Period::Yearly,
+                utc_at(2026, 10, 26, 1, 30),
+                Tz::Europe__London
+            ),
+            Some(utc_at(2025, 10, 26, 0, 30))
+        );
+    }
+
+    #[test]
+    fn calendar_periods_start_at_midnight_across_dst_changes() {
+        let now = utc_at(2025, 3, 31, 12, 0);
+
+        assert_eq!(
+            start_date(Period::Day, now, Tz::Europe__London),
+            Some(utc(2025, 3, 30, 23))
+        );
+        assert_eq!(
+            start_date(Period::Week, now, Tz::Europe__London),
+            Some(utc(2025, 3, 30, 23))
+        );
+        assert_eq!(
+            start_date(Period::Month, now, Tz::Europe__London),
+            Some(utc(2025, 3, 1, 0))
+        );
+        assert_eq!(
+            start_date(Period::Year, now, Tz::Europe__Paris),
+            Some(utc(2024, 12, 31, 23))
+        );
+        assert_eq!(
+            start_date(Period::Unknown, now, Tz::UTC),
+            Some(DateTime::default())
+        );
+    }
+
+    #[test]
+    fn ranges_contain_their_included_bounds_only() {
+        let range = DateRange {
+            start: Bound::Included(utc(2025, 3, 1, 0)),
+            end: Bound::Excluded(utc(2025, 4, 1, 0)),
+        };
+
+        assert!(range.contains(&utc(2025, 3, 1, 0)));
+        assert!(range.contains(&utc(2025, 3, 31, 23)));
+        assert!(!range.contains(&utc(2025, 4, 1, 0)));
+        assert!(!range.contains(&utc(2025, 2, 28, 23)));
+        assert!(DateRange::default().contains(&utc(1970, 1, 1, 0)));
+    }
+}
@@ -0,0 +1,287 @@
+//! Errors of first_top and their exit codes.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// Errors of first_top, grouped by what went wrong so callers (and the exit code) can tell them apart.
+#[derive(Debug)]
+pub enum Error {
+    /// Invalid command line arguments.
+    Usage(String),
+    /// A valid command line asking for something that does not make sense (missing channel, bad time span).
+    Query(String),
+    /// Invalid configuration file, environment variables or rules.
+    Config(String),
+    /// Nothing matched the query.
+    NoData(String),
+    /// The check of a collection found this many problems.
+    Check(usize),
+    /// A result names a timezone that is not in the IANA database.
+    Timezone(String),
+    /// The opening time or delta of a day could not be computed.
+    Time(&'static str),
+    /// Another process held the lock of a collection for longer than the timeout.
+    Locked {
+        /// The collection.
+        path: PathBuf,
+        /// How long the lock was waited for.
+        timeout: Duration,
+    },
+    /// A file could not be opened, read or written.
+    Io {
+        /// The file.
+        path: PathBuf,
+        /// What failed.
+        source: io::Error,
+    },
+    /// A row of a collection file has the wrong number of fields or an invalid value.
+    Record {
+        /// The collection file.
+        path: PathBuf,
+        /// Line of the row, starting at 1.
+        line: u64,
+        /// What is wrong with the row.
+        message: String,
+    },
+    /// A collection file is not valid CSV, at the given line if known.
+    Csv {
+        /// The collection file.
+        path: PathBuf,
+        /// Line of the error, starting at 1.
+        line: Option<u64>,
+        /// What the CSV reader or writer failed on.
+        source: csv::Error,
+    },
+    /// A SQLite database could not be opened, read or written.
+    #[cfg(feature = "sqlite")]
+    Sqlite {
+        /// The database file.
+        path: PathBuf,
+        /// What SQLite failed on.
+        source: rusqlite::Error,
+    },
+    /// A table of a SQLite database, or one of its rows, has an invalid layout or value.
+    Table {
+        /// The database file.
+        path: PathBuf,
+        /// The table of the collection.
+        table: String,
+        /// Row id of the invalid row, if a row is.
+        rowid: Option<i64>,
+        /// What is wrong with the table or row.
+        message: String,
+    },
+}
+
+/// Result of the operations of first_top, failing with an [`Error`] by default.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// Wrap a csv error about a file, keeping I/O failures apart from malformed data.
+    pub fn csv(path: impl Into<PathBuf>, source: csv::Error) -> Self {
+        let path = path.into();
+        let line = source.position().map(|position| position.line());
+
+        if !source.is_io_error() {
+            return Error::Csv { path, line, source };
+        }
+
+        match source.into_kind() {
+            csv::ErrorKind::Io(source) => Error::Io { path, source },
+            kind => Error::Io {
+                path,
+                source: io::Error::other(format!("{kind:?}")),
+            },
+        }
+    }
+
+    /// Exit code of the process, following sysexits.h where it has a matching class.
+    pub fn exit_code(&self) -> ExitCode {
+        let code = match self {
+            Error::NoData(_) => 1,
+            Error::Usage(_) | Error::Query(_) => 64,
+            Error::Check(_)
+            | Error::Timezone(_)
+            | Error::Time(_)
+            | Error::Record { .. }
+            | Error::Csv { .. }
+            | Error::Table { .. } => 65,
+            Error::Io { source, .. } => match source.kind() {
+                io::ErrorKind::NotFound => 66,
+                io::ErrorKind::PermissionDenied => 77,
+                _ => 74,
+            },
+            #[cfg(feature = "sqlite")]
+            Error::Sqlite { .. } => 74,
+            Error::Locked { .. } => 75,
+            Error::Config(_) => 78,
+        };
+
+        ExitCode::from(code)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage(message)
+            | Error::Query(message)
+            | Error::Config(message)
+            | Error::NoData(message) => write!(f, "{message}"),
+            Error::Check(count) => write!(f, "Found {count} problems"),
+            Error::Timezone(name) => write!(f, "Unknown timezone {name}"),
+            Error::Time(message) => write!(f, "{message}"),
+            Error::Locked { path, timeout } => write!(
+                f,
+                "Timed out after {} ms waiting for the lock of {}, which another process is using",
+                timeout.as_millis(),
+                path.display()
+            ),
+            Error::Io { path, .. } => write!(f, "Could not access {}", path.display()),
+            Error::Record {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{line}: {message}", path.display()),
+            Error::Csv {
+                path,
+                line: Some(line),
+                ..
+            } => write!(f, "{}:{line}: invalid CSV", path.display()),
+            Error::Csv { path, .. } => write!(f, "{}: invalid CSV", path.display()),
+            #[cfg(feature = "sqlite")]
+            Error::Sqlite { path, .. } => {
+                write!(f, "Could not use the SQLite database {}", path.display())
+            }
+            Error::Table {
+                path,
+                table,
+                rowid: Some(rowid),
+                message,
+            } => write!(
+                f,
+                "{}: row id {rowid} of {table}: {message}",
+                path.display()
+            ),
+            Error::Table {
+                path,
+                table,
+                message,
+                ..
+            } => write!(f, "{}: {table}: {message}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Csv { source, .. } => Some(source),
+            #[cfg(feature = "sqlite")]
+            Error::Sqlite { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn io(kind: io::ErrorKind) -> Error {
+        Error::Io {
+            path: PathBuf::from("results.csv"),
+            source: io::Error::from(kind),
+        }
+    }
+
+    fn invalid_csv() -> csv::Error {
+        csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader("a,b\nc\n".as_bytes())
+            .records()
+            .find_map(|record| record.err())
+            .unwrap()
+    }
+
+    #[test]
+    fn exit_codes_follow_sysexits() {
+        let path = PathBuf::from("results.csv");
+        let codes = [
+            (Error::NoData(String::from("none")), 1),
+            (Error::Usage(String::from("usage")), 64),
+            (Error::Query(String::from("query")), 64),
+            (Error::Check(3), 65),
+            (Error::Timezone(String::from("Mars/Olympus")), 65),
+            (Error::Time("no opening"), 65),
+            (
+                Error::Record {
+                    path: path.clone(),
+                    line: 2,
+                    message: String::from("invalid"),
+                },
+                65,
+            ),
+            (Error::csv(&path, invalid_csv()), 65),
+            (io(io::ErrorKind::NotFound), 66),
+            (io(io::ErrorKind::PermissionDenied), 77),
+            (io(io::ErrorKind::Other), 74),
+            (
+                Error::csv(
+                    &path,
+                    csv::Error::from(io::Error::from(io::ErrorKind::NotFound)),
+                ),
+                66,
+            ),
+            (
+                Error::Table {
+                    path: path.clone(),
+                    table: String::from("first_results"),
+                    rowid: Some(2),
+                    message: String::from("invalid"),
+                },
+                65,
+            ),
+            (
+                Error::Locked {
+                    path,
+                    timeout: Duration::from_secs(1),
+                },
+                75,
+            ),
+            (Error::Config(String::from("config")), 78),
+        ];
+
+        for (error, code) in codes {
+            assert_eq!(error.exit_code(), ExitCode::from(code), "{error:?}");
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_errors_keep_their_source() {
+        let error = Error::Sqlite {
+            path: PathBuf::from("first_top.sqlite"),
+            source: rusqlite::Error::QueryReturnedNoRows,
+        };
+
+        assert_eq!(error.exit_code(), ExitCode::from(74));
+        assert!(matches!(
+            std::error::Error::source(&error)
+                .and_then(|source| source.downcast_ref::<rusqlite::Error>()),
+            Some(rusqlite::Error::QueryReturnedNoRows)
+        ));
+    }
+
+    #[test]
+    fn csv_errors_keep_their_line() {
+        let error = Error::csv("results.csv", invalid_csv());
+
+        assert!(matches!(error, Error::Csv { line: Some(2), .. }));
+        assert_eq!(error.to_string(), "results.csv:2: invalid CSV");
+    }
+}
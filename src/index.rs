@@ -0,0 +1,463 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Bound;
+use std::path::Path;
+
+use chrono::{NaiveDate, TimeDelta};
+
+use crate::FirstResult;
+use crate::dates::DateRange;
+
+/// First line of an index file, followed by its version.
+const INDEX_MARKER: &str = "#index ";
+
+/// Version of the layout of index files, to be increased whenever it changes.
+const INDEX_VERSION: u32 = 1;
+
+/// Part of a collection file to read, from the row at start to end (or the end of the file).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Span {
+    pub start: u64,
+    pub end: Option<u64>,
+    /// Line number of the row at start.
+    pub line: u64,
+}
+
+/// Where a row starts in a collection file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Position {
+    pub offset: u64,
+    pub line: u64,
+}
+
+/// The rows of a UTC day of a collection of results.
+#[derive(Debug, PartialEq)]
+struct Day {
+    day: NaiveDate,
+    /// Where the first row of the day starts, the rows of the day running up to the next day.
+    start: Position,
+    /// Lowercase names of the channels played in on the day.
+    channels: Vec<String>,
+}
+
+/// Index of a collection of results, kept next to it in the `.<collection>.<extension>.index` file.
+///
+/// The bot appends results in the order they are played, so the rows of each UTC day follow each other
+/// and a range of days can be read by seeking to its first row. The index records where each day starts
+/// and the channels played in on it, and is brought up to date by reading the rows appended since.
+/// Invalid rows belong to the day of the valid row before them.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Index {
+    /// Length of the collection indexed, up to the end of its last row.
+    len: u64,
+    /// Number of lines indexed.
+    lines: u64,
+    /// Where the last row indexed starts, which must still be there for the index to apply.
+    last_row: Option<Position>,
+    /// Contents of the last row indexed, which tell a collection appended to from a rewritten one.
+    last_contents: Vec<u8>,
+    /// Whether a row went back to an earlier day than the one before it, which makes the index unusable.
+    unordered: bool,
+    /// First row read while no day has started, which the first day starts at.
+    first_row: Option<Position>,
+    days: Vec<Day>,
+}
+
+impl Index {
+    /// Read the index file of a collection, if there is a valid one.
+    pub fn read(path: &Path) -> Option<Self> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(path)
+            .ok()?;
+        let mut records = rdr.records();
+        let mut next = || records.next()?.ok();
+
+        let marker = next()?;
+        if marker.get(0)?.strip_prefix(INDEX_MARKER)?.parse() != Ok(INDEX_VERSION) {
+            return None;
+        }
+
+        let header = next()?;
+        let position = |offset: &str, line: &str| {
+            Some(Position {
+                offset: offset.parse().ok()?,
+                line: line.parse().ok()?,
+            })
+        };
+        let mut index = Index {
+            len: header.get(0)?.parse().ok()?,
+            lines: header.get(1)?.parse().ok()?,
+            last_row: match (header.get(2)?, header.get(3)?) {
+                ("", "") => None,
+                (offset, line) => Some(position(offset, line)?),
+            },
+            last_contents: Vec::from(header.get(4)?),
+            unordered: header.get(5)? == "unordered",
+            first_row: None,
+            days: Vec::new(),
+        };
+
+        while let Some(day) = next() {
+            index.days.push(Day {
+                day: day.get(0)?.parse().ok()?,
+                start: position(day.get(1)?, day.get(2)?)?,
+                channels: day.iter().skip(3).map(String::from).collect(),
+            });
+        }
+
+        index.is_consistent().then_some(index)
+    }
+
+    /// Whether the rows recorded lie within the collection indexed, each day starting after the one before it,
+    /// which a stale or damaged index file may not hold to.
+    fn is_consistent(&self) -> bool {
+        let within =
+            |position: &Position| position.offset <= self.len && position.line <= self.lines;
+        let rows_within = match self.last_row {
+            Some(last_row) => {
+                within(&last_row)
+                    && self.days.iter().all(|day| {
+                        day.start.offset <= last_row.offset && day.start.line <= last_row.line
+                    })
+            }
+            None => self.days.is_empty(),
+        };
+
+        rows_within
+            && self.days.windows(2).all(|days| {
+                days[0].day < days[1].day
+                    && days[0].start.offset < days[1].start.offset
+                    && days[0].start.line < days[1].start.line
+            })
+    }
+
+    /// Write the index file of a collection, replacing the previous one at once.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}.tmp", std::process::id()));
+        let temp = Path::new(&name);
+
+        let result = self.write_to(temp).and_then(|()| fs::rename(temp, path));
+
+        if result.is_err() {
+            let _ = fs::remove_file(temp);
+        }
+
+        result
+    }
+
+    fn write_to(&self, temp: &Path) -> io::Result<()> {
+        let mut wtr = csv::WriterBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(temp)?;
+        let last_row = self
+            .last_row
+            .map(|row| [row.offset.to_string(), row.line.to_string()])
+            .unwrap_or_default();
+
+        wtr.write_record([format!("{INDEX_MARKER}{INDEX_VERSION}")])?;
+        wtr.write_record([
+            self.len.to_string().as_bytes(),
+            self.lines.to_string().as_bytes(),
+            last_row[0].as_bytes(),
+            last_row[1].as_bytes(),
+            &self.last_contents,
+            if self.unordered {
+                b"unordered"
+            } else {
+                b"ordered"
+            },
+        ])?;
+
+        for day in &self.days {
+            wtr.write_record(
+                [
+                    day.day.to_string(),
+                    day.start.offset.to_string(),
+                    day.start.line.to_string(),
+                ]
+                .into_iter()
+                .chain(day.channels.iter().cloned()),
+            )?;
+        }
+
+        wtr.into_inner()
+            .map_err(|error| error.into_error())?
+            .sync_all()
+    }
+
+    /// Whether the collection is the one indexed, possibly with rows appended since.
+    pub fn applies_to(&self, file: &mut File) -> io::Result<bool> {
+        let Some(last_row) = self.last_row else {
+            return Ok(self.len == 0);
+        };
+
+        if file.metadata()?.len() < self.len {
+            return Ok(false);
+        }
+
+        let mut contents = vec![0; (self.len - last_row.offset) as usize];
+
+        file.seek(SeekFrom::Start(last_row.offset))?;
+        file.read_exact(&mut contents)?;
+
+        Ok(contents == self.last_contents)
+    }
+
+    /// The part of the collection that is not indexed yet, or all of it for an empty index.
+    pub fn unindexed(&self) -> Option<Span> {
+        self.last_row.map(|_| Span {
+            start: self.len,
+            end: None,
+            line: self.lines + 1,
+        })
+    }
+
+    /// Index a row, with the result it holds if it is valid.
+    pub fn add(&mut self, start: Position, result: Option<&FirstResult>) {
+        self.last_row = Some(start);
+        self.first_row.get_or_insert(start);
+
+        let Some(result) = result else {
+            return;
+        };
+        let day = result.datetime.date_naive();
+        let channel = result.channel.to_lowercase();
+
+        match self.days.last_mut() {
+            Some(last) if last.day == day => {
+                if !last.channels.contains(&channel) {
+                    last.channels.push(channel);
+                }
+            }
+            Some(last) if last.day > day => self.unordered = true,
+            last => {
+                let start = match last {
+                    Some(_) => start,
+                    None => self.first_row.unwrap_or(start),
+                };
+
+                self.days.push(Day {
+                    day,
+                    start,
+                    channels: vec![channel],
+                });
+            }
+        }
+    }
+
+    /// Record where the rows indexed end, with the contents of the last one read back from the collection.
+    pub fn end(&mut self, len: u64, lines: u64, file: &mut File) -> io::Result<()> {
+        self.len = len;
+        self.lines = lines;
+        self.last_contents.clear();
+
+        if let Some(last_row) = self.last_row {
+            file.seek(SeekFrom::Start(last_row.offset))?;
+            file.take(len - last_row.offset)
+                .read_to_end(&mut self.last_contents)?;
+        }
+
+        Ok(())
+    }
+
+    /// The parts of the collection holding the rows of a channel (in any casing), or of all channels,
+    /// played within range, and the rows appended since the index was brought up to date.
+    ///
+    /// Those rows still have to be filtered, as the parts hold every row of their days.
+    /// There are none when the rows are not in chronological order.
+    pub fn spans(&self, channel: Option<&str>, range: &DateRange) -> Option<Vec<Span>> {
+        if self.unordered {
+            return None;
+        }
+
+        let channel = channel.map(str::to_lowercase);
+        let first_day = match range.start {
+            Bound::Included(start) | Bound::Excluded(start) => Some(start.date_naive()),
+            Bound::Unbounded => None,
+        };
+        let last_day = match range.end {
+            Bound::Included(end) => Some(end.date_naive()),
+            Bound::Excluded(end) => Some((end - TimeDelta::nanoseconds(1)).date_naive()),
+            Bound::Unbounded => None,
+        };
+        let mut spans: Vec<Span> = Vec::new();
+
+        for (i, day) in self.days.iter().enumerate() {
+            if first_day.is_some_and(|first_day| day.day < first_day)
+                || last_day.is_some_and(|last_day| day.day > last_day)
+                || channel
+                    .as_ref()
+                    .is_some_and(|channel| !day.channels.contains(channel))
+            {
+                continue;
+            }
+
+            let end = self
+                .days
+                .get(i + 1)
+                .map_or(self.len, |next| next.start.offset);
+
+            match spans.last_mut() {
+                Some(last) if last.end == Some(day.start.offset) => last.end = Some(end),
+                _ => spans.push(Span {
+                    start: day.start.offset,
+                    end: Some(end),
+                    line: day.start.line,
+                }),
+            }
+        }
+
+        spans.push(Span {
+            start: self.len,
+            end: None,
+            line: self.lines + 1,
+        });
+
+        Some(spans)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn result(channel: &str, day: u32) -> FirstResult {
+        FirstResult {
+            nick: String::from("alice"),
+            channel: String::from(channel),
+            datetime: Utc.with_ymd_and_hms(2025, 3, day, 9, 0, 0).unwrap(),
+            timezone: String::from("Europe/Paris"),
+        }
+    }
+
+    fn position(offset: u64, line: u64) -> Position {
+        Position { offset, line }
+    }
+
+    fn index(rows: &[Option<FirstResult>]) -> Index {
+        let mut index = Index::default();
+
+        for (i, row) in rows.iter().enumerate() {
+            index.add(position(i as u64 * 10, i as u64 + 1), row.as_ref());
+        }
+
+        index.len = rows.len() as u64 * 10;
+        index.lines = rows.len() as u64;
+        index
+    }
+
+    fn span(start: u64, end: Option<u64>, line: u64) -> Span {
+        Span { start, end, line }
+    }
+
+    fn days(from: u32, to: u32) -> DateRange {
+        DateRange {
+            start: Bound::Included(Utc.with_ymd_and_hms(2025, 3, from, 0, 0, 0).unwrap()),
+            end: Bound::Excluded(Utc.with_ymd_and_hms(2025, 3, to, 0, 0, 0).unwrap()),
+        }
+    }
+
+    #[test]
+    fn spans_cover_the_days_of_the_range_and_the_rows_appended_since() {
+        let index = index(&[
+            Some(result("#a", 1)),
+            Some(result("#a", 2)),
+            None,
+            Some(result("#b", 2)),
+            Some(result("#a", 3)),
+            Some(result("#a", 4)),
+        ]);
+
+        assert_eq!(
+            index.spans(None, &days(2, 4)),
+            Some(vec![span(10, Some(50), 2), span(60, None, 7)])
+        );
+        assert_eq!(
+            index.spans(None, &DateRange::default()),
+            Some(vec![span(0, Some(60), 1), span(60, None, 7)])
+        );
+    }
+
+    #[test]
+    fn spans_skip_the_days_without_the_channel() {
+        let index = index(&[
+            Some(result("#a", 1)),
+            Some(result("#B", 2)),
+            Some(result("#a", 3)),
+        ]);
+
+        assert_eq!(
+            index.spans(Some("#b"), &DateRange::default()),
+            Some(vec![span(10, Some(20), 2), span(30, None, 4)])
+        );
+        assert_eq!(
+            index.spans(Some("#A"), &DateRange::default()),
+            Some(vec![
+                span(0, Some(10), 1),
+                span(20, Some(30), 3),
+                span(30, None, 4)
+            ])
+        );
+    }
+
+    #[test]
+    fn leading_invalid_rows_belong_to_the_first_day() {
+        let index = index(&[None, Some(result("#a", 2))]);
+
+        assert_eq!(
+            index.spans(Some("#a"), &days(2, 3)),
+            Some(vec![span(0, Some(20), 1), span(20, None, 3)])
+        );
+    }
+
+    #[test]
+    fn unordered_rows_make_the_index_unusable() {
+        let index = index(&[Some(result("#a", 2)), Some(result("#a", 1))]);
+
+        assert_eq!(index.spans(None, &DateRange::default()), None);
+    }
+
+    #[test]
+    fn indexes_out_of_the_collection_are_discarded() {
+        let dir = TempDir::new("bad-index");
+        let path = dir.join("index");
+        let header = |len: u64, last_row: u64, contents: &str| {
+            format!("#index 1\n{len},3,{last_row},3,{contents},ordered\n")
+        };
+        let read = |contents: String| {
+            fs::write(&path, contents).unwrap();
+            Index::read(&path)
+        };
+
+        assert!(read(header(30, 40, "") + "2025-03-01,0,1,#a\n").is_none());
+        assert!(
+            read(header(30, 20, "row 3") + "2025-03-01,0,1,#a\n2025-03-02,40,2,#a\n").is_none()
+        );
+        assert!(
+            read(header(30, 20, "row 3") + "2025-03-01,10,2,#a\n2025-03-02,0,1,#a\n").is_none()
+        );
+        assert!(
+            read(header(30, 20, "row 3") + "2025-03-01,0,1,#a\n2025-03-02,10,2,#a\n").is_some()
+        );
+    }
+
+    #[test]
+    fn indexes_are_read_back_as_written() {
+        let mut index = index(&[Some(result("#a", 1)), Some(result("#b,\"c\"", 2))]);
+        index.first_row = None;
+        index.last_contents = Vec::from("alice,\"#b,\"\"c\"\"\",2025-03-02 09:00:00 UTC\n");
+        let dir = TempDir::new("index");
+        let path = dir.join("index");
+
+        index.write(&path).unwrap();
+
+        assert_eq!(Index::read(&path), Some(index));
+    }
+}
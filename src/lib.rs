@@ -0,0 +1,506 @@
+//! Rankings of the !1st game played on IRC, where players race to type !1st right after
+//! a daily opening time that the bot draws at random.
+//!
+//! The results of the players are read from a [`database::Database`] collection of [`FirstResult`]
+//! (those of the channel and dates of a [`query::Query`] with [`query::select_results`]),
+//! grouped by day with [`daily_deltas`] (each attempt's gap to the opening time of its day)
+//! and then ranked with [`rank`], [`ranking::standings`], [`ranking::personal_bests`]
+//! or summarised with [`stats::player_stats`].
+//!
+//! The reports the binary prints are built with [`reports`] (a leaderboard with [`reports::rank_report`],
+//! to render as IRC lines with [`output::Report::irc`]), and results are imported, exported
+//! and migrated with [`transfer`].
+
+#![warn(missing_docs)]
+
+pub mod check;
+pub mod config;
+pub mod database;
+pub mod dates;
+pub mod error;
+mod index;
+pub mod openings;
+pub mod output;
+pub mod query;
+pub mod ranking;
+pub mod reports;
+pub mod rules;
+pub mod schedule;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
+pub mod storage;
+#[cfg(test)]
+mod test_support;
+pub mod transfer;
+
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use itertools::Itertools;
+
+use crate::database::CsvRecord;
+use crate::error::{Error, Result};
+use crate::ranking::Attempt;
+use crate::rules::{OpeningMode, Rules};
+
+/// A !1st played by a player, as stored by the bot.
+#[derive(Debug, PartialEq)]
+pub struct FirstResult {
+    /// Nick of the player.
+    pub nick: String,
+    /// Channel played in, in the casing the bot saw.
+    pub channel: String,
+    /// When the player played.
+    pub datetime: DateTime<Utc>,
+    /// IANA name of the player's timezone, in which the opening time of the day applies.
+    pub timezone: String,
+}
+
+impl CsvRecord for FirstResult {
+    const HEADERS: &'static [&'static str] = &["nick", "channel", "datetime", "timezone"];
+
+    fn from_fields(fields: &[String]) -> Result<Self, String> {
+        let [nick, channel, datetime, timezone] = fields else {
+            return Err(format!("expected 4 fields, found {}", fields.len()));
+        };
+
+        if timezone.parse::<Tz>().is_err() {
+            return Err(format!("unknown timezone {timezone}"));
+        }
+
+        Ok(Self {
+            nick: nick.clone(),
+            channel: channel.clone(),
+            datetime: datetime
+                .parse()
+                .map_err(|_| format!("invalid timestamp {datetime}"))?,
+            timezone: timezone.clone(),
+        })
+    }
+
+    fn to_fields(&self) -> Vec<String> {
+        vec![
+            self.nick.clone(),
+            self.channel.clone(),
+            self.datetime.to_string(),
+            self.timezone.clone(),
+        ]
+    }
+}
+
+/// A period of time to go back from now, see [`dates::start_date`].
+#[derive(Debug, Clone, Copy)]
+pub enum Period {
+    /// Since midnight.
+    Day,
+    /// The last 24 hours.
+    Daily,
+    /// Since Monday.
+    Week,
+    /// The last 7 days.
+    Weekly,
+    /// Since the first of the month.
+    Month,
+    /// The last 30 days.
+    Monthly,
+    /// Since the first of January.
+    Year,
+    /// The last 365 days.
+    Yearly,
+    /// All the results.
+    Unknown,
+}
+
+impl Period {
+    /// Parse the name of a period as given on the command line, where "all" means no period.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "daily" => Some(Period::Daily),
+            "day" | "today" => Some(Period::Day),
+            "week" => Some(Period::Week),
+            "weekly" => Some(Period::Weekly),
+            "month" => Some(Period::Month),
+            "monthly" => Some(Period::Monthly),
+            "year" => Some(Period::Year),
+            "yearly" => Some(Period::Yearly),
+            "all" => Some(Period::Unknown),
+            _ => None,
+        }
+    }
+}
+
+/// Days with their winning attempt, as returned by [`rank`] and [`daily_winners`].
+pub type Rank = Vec<(Result<NaiveDate>, Vec<Attempt>)>;
+
+/// Days with all their attempts, lowest delta first, as returned by [`daily_deltas`].
+pub type Deltas = Vec<(NaiveDate, Vec<Attempt>)>;
+
+/// Compute the top rules.max_results earliest !1st submissions for each nick.
+///
+/// 1. Find the winner of each day (see daily_winners).
+/// 2. Globally sort all days by delta time (earliest !1st).
+/// 3. Ensure unique entries by nick.
+/// 4. Return the top rules.max_results.
+pub fn rank(first_results: &[FirstResult], rules: &Rules) -> Result<Rank> {
+    // Sort the days by the lowest delta, make results unique by nick and take max_results.
+    let rank: Rank = daily_winners(first_results, rules)
+        .into_iter()
+        .sorted_by(|a, b| Ord::cmp(&a.1[0].delta_us, &b.1[0].delta_us))
+        .unique_by(|r| r.1[0].nick.clone())
+        .take(rules.max_results)
+        .collect();
+
+    Ok(rank)
+}
+
+/// Compute the deltas of every attempt of each day, lowest first, with the days in chronological order.
+///
+/// 1. Group entries by date (each different day of the year is a key for the group).
+/// 2. For each date compute each player's "delta" (how close they were to the opening time).
+pub fn daily_deltas(first_results: &[FirstResult], rules: &Rules) -> Deltas {
+    // Group entries by date (each different day of the year is a key for the group).
+    // The results are in UTC order, so the game days of players in different timezones interleave
+    // and each day gathers all of its results rather than only those next to each other.
+    let groups = first_results
+        .iter()
+        .filter_map(|r| Some((game_day(r, rules)?, r)))
+        .into_group_map();
+
+    // For each group (one per date), calculate the deltas and sort them by lowest.
+    // Map each to Vec<Attempt>, the delta and opening time of each player.
+    groups
+        .into_iter()
+        .sorted_by_key(|(day, _)| *day)
+        .map(|(day, group)| {
+            let attempts: Vec<Attempt> = group
+                .into_iter()
+                .filter_map(|r| attempt(day, r, rules).ok())
+                .sorted_by(|a, b| Ord::cmp(&a.delta_us, &b.delta_us))
+                .collect();
+
+            (day, attempts)
+        })
+        .collect()
+}
+
+/// The wall clock opening time of a day of a channel: the one recorded for the day if any,
+/// and otherwise the one computed again from the schedule.
+pub fn day_opening(day: NaiveDate, channel: &str, rules: &Rules) -> Result<NaiveTime> {
+    match rules.openings.get(channel, day) {
+        Some(time) => Ok(time),
+        None => rules
+            .schedule
+            .opening(day, &rules.open_hour, &rules.open_min)
+            .ok_or(Error::Time("Bad time format")),
+    }
+}
+
+/// The day a result was played on, in the timezone its opening time applies in:
+/// the player's own timezone, or the channel timezone with [`OpeningMode::Channel`].
+pub fn game_day(r: &FirstResult, rules: &Rules) -> Option<NaiveDate> {
+    let tz: Tz = match rules.opening_mode {
+        OpeningMode::Player => r.timezone.parse().ok()?,
+        OpeningMode::Channel => rules.timezone,
+    };
+
+    Some(r.datetime.with_timezone(&tz).date_naive())
+}
+
+/// Compute the winner of each day, in chronological order.
+///
+/// For each date, pick the earliest delta and keep it if it is of interest (positive and below cutoff).
+pub fn daily_winners(first_results: &[FirstResult], rules: &Rules) -> Rank {
+    daily_deltas(first_results, rules)
+        .into_iter()
+        .filter_map(|(day, attempts)| {
+            if let Some(winner) = attempts.first()
+                && winner.delta_us > 0
+                && winner.delta_us <= rules.cutoff_us
+            {
+                return Some((Ok(day), attempts.into_iter().take(1).collect()));
+            }
+            None
+        })
+        .collect()
+}
+
+/// Calculate the delta and the opening time of a player's !1st.
+pub fn attempt(day: NaiveDate, r: &FirstResult, rules: &Rules) -> Result<Attempt> {
+    let (delta_us, nick) = delta(day, r, rules)?;
+
+    Ok(Attempt {
+        nick,
+        delta_us,
+        opening: opening_time(day, r, rules)?,
+    })
+}
+
+/// Calculate the delta in microseconds between the time when the user played !1st and the opening time.
+pub fn delta(day: NaiveDate, r: &FirstResult, rules: &Rules) -> Result<(i64, String)> {
+    let local_opening_time = opening_time(day, r, rules)?;
+
+    // Finally subtract the local opening time from the player time.
+    let delta = r.datetime.signed_duration_since(local_opening_time);
+
+    Ok((
+        delta
+            .num_microseconds()
+            .ok_or(Error::Time("Could not get microseconds"))?,
+        r.nick.clone(),
+    ))
+}
+
+/// Calculate the opening time of a day in the timezone of the player,
+/// or in the channel timezone if the opening mode of the rules is [`OpeningMode::Channel`].
+///
+/// The opening time recorded for the day is used when there is one, and otherwise it is
+/// computed again from the schedule of the channel, which only holds while the bot's rule is unchanged.
+pub fn opening_time(day: NaiveDate, r: &FirstResult, rules: &Rules) -> Result<DateTime<Tz>> {
+    let time = day_opening(day, &r.channel, rules)?;
+
+    if rules.opening_mode == OpeningMode::Channel {
+        // The earliest of the two instants when clocks go back, and none when they skip it.
+        return rules
+            .timezone
+            .from_local_datetime(&day.and_time(time))
+            .earliest()
+            .ok_or(Error::Time(
+                "The opening time does not exist in the channel timezone",
+            ));
+    }
+
+    // Convert the player time to the player timezone.
+    let tz: Tz = r
+        .timezone
+        .parse()
+        .map_err(|_| Error::Timezone(r.timezone.clone()))?;
+    let local_player_time = r.datetime.with_timezone(&tz);
+
+    // To build the local opening time we use a little trick.
+    // We already know the opening time of the day, but we are working with DateTime.
+    // So we make the local opening time equal to the local player time to get the correct date.
+    // Then we simply set the opening hour, minute and second.
+    // Finally we zero out the other components of the DateTime.
+    local_player_time
+        .with_hour(time.hour())
+        .and_then(|t| t.with_minute(time.minute()))
+        .and_then(|t| t.with_second(time.second()))
+        .and_then(|t| t.with_nanosecond(time.nanosecond()))
+        .ok_or(Error::Time("Bad time format"))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+
+    use chrono_tz::Europe::Paris;
+
+    use super::*;
+    use crate::openings::{Opening, Openings};
+    use crate::schedule::OpeningSchedule;
+
+    fn result(nick: &str, timezone: &str, datetime: &str) -> FirstResult {
+        FirstResult {
+            nick: String::from(nick),
+            channel: String::from("#test"),
+            datetime: datetime.parse().unwrap(),
+            timezone: String::from(timezone),
+        }
+    }
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, day).unwrap()
+    }
+
+    fn time(hour: u32, min: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, min, 0).unwrap()
+    }
+
+    /// Rules opening at 08:00, except on the days recorded at other times.
+    fn recorded(openings: &[(&str, u32, NaiveTime)]) -> Rules {
+        Rules {
+            openings: Openings::new(
+                openings
+                    .iter()
+                    .map(|(channel, d, time)| Opening {
+                        channel: String::from(*channel),
+                        day: day(*d),
+                        time: *time,
+                        rule: String::from("seeded-v1"),
+                    })
+                    .collect(),
+            ),
+            ..at_eight()
+        }
+    }
+
+    /// Rules opening at time in the Paris timezone for every player.
+    fn in_paris(time: NaiveTime) -> Rules {
+        Rules {
+            schedule: OpeningSchedule::Fixed(time),
+            opening_mode: OpeningMode::Channel,
+            timezone: Paris,
+            ..Rules::default()
+        }
+    }
+
+    fn at_eight() -> Rules {
+        Rules {
+            schedule: OpeningSchedule::Fixed(NaiveTime::from_hms_opt(8, 0, 0).unwrap()),
+            ..Rules::default()
+        }
+    }
+
+    /// The nicks and deltas in milliseconds of each day.
+    fn deltas(days: &Deltas) -> Vec<(NaiveDate, Vec<(&str, i64)>)> {
+        days.iter()
+            .map(|(day, attempts)| {
+                let attempts = attempts
+                    .iter()
+                    .map(|attempt| (attempt.nick.as_str(), attempt.delta_us / 1000))
+                    .collect();
+
+                (*day, attempts)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn days_gather_the_results_of_players_in_different_timezones() {
+        // Bob plays the 15th in Tokyo before Carol plays the 14th in London.
+        let results = [
+            result("alice", "Europe/London", "2025-03-14 08:00:00.200 UTC"),
+            result("bob", "Asia/Tokyo", "2025-03-14 23:00:00.300 UTC"),
+            result("carol", "Europe/London", "2025-03-14 23:30:00 UTC"),
+            result("dave", "Europe/London", "2025-03-15 08:00:00.100 UTC"),
+        ];
+        let rules = at_eight();
+
+        assert_eq!(
+            deltas(&daily_deltas(&results, &rules)),
+            [
+                (day(14), vec![("alice", 200), ("carol", 55_800_000)]),
+                (day(15), vec![("dave", 100), ("bob", 300)]),
+            ]
+        );
+        assert_eq!(
+            daily_winners(&results, &rules)
+                .into_iter()
+                .map(|(day, attempts)| (day.unwrap(), attempts[0].nick.clone()))
+                .collect::<Vec<_>>(),
+            [
+                (day(14), String::from("alice")),
+                (day(15), String::from("dave"))
+            ]
+        );
+    }
+
+    #[test]
+    fn recorded_openings_take_precedence_over_the_schedule() {
+        let rules = recorded(&[("#Test", 14, time(9, 30)), ("#other", 15, time(10, 0))]);
+
+        assert_eq!(day_opening(day(14), "#test", &rules).unwrap(), time(9, 30));
+        // Other days and channels fall back on the schedule.
+        assert_eq!(day_opening(day(15), "#test", &rules).unwrap(), time(8, 0));
+        assert_eq!(day_opening(day(14), "#other", &rules).unwrap(), time(8, 0));
+    }
+
+    #[test]
+    fn deltas_are_measured_from_the_recorded_opening() {
+        let rules = recorded(&[("#test", 14, time(9, 30))]);
+        let results = [
+            result("alice", "Europe/Paris", "2025-03-14 08:30:00.250 UTC"),
+            result("bob", "Europe/Paris", "2025-03-15 07:00:00.400 UTC"),
+        ];
+
+        assert_eq!(
+            deltas(&daily_deltas(&results, &rules)),
+            [
+                (day(14), vec![("alice", 250)]),
+                (day(15), vec![("bob", 400)])
+            ]
+        );
+        assert_eq!(
+            opening_time(day(14), &results[0], &rules).unwrap(),
+            Paris.with_ymd_and_hms(2025, 3, 14, 9, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn the_seeded_schedule_applies_without_recorded_openings() {
+        let rules = Rules::default();
+        // The bot opens at 10:49 on the 14th of any month.
+        let results = [result("alice", "UTC", "2025-03-14 10:49:00.300 UTC")];
+
+        assert_eq!(day_opening(day(14), "#test", &rules).unwrap(), time(10, 49));
+        assert_eq!(
+            deltas(&daily_deltas(&results, &rules)),
+            [(day(14), vec![("alice", 300)])]
+        );
+    }
+
+    #[test]
+    fn channel_openings_are_the_same_instant_in_every_timezone() {
+        // 09:00 in Paris is 08:00 UTC, and 17:00 in Tokyo or 04:00 in New York.
+        let rules = in_paris(time(9, 0));
+        let results = [
+            result("alice", "Asia/Tokyo", "2025-03-14 08:00:00.200 UTC"),
+            result("bob", "America/New_York", "2025-03-14 08:00:00.500 UTC"),
+            // Already the 15th in Tokyo, but still the 14th in Paris.
+            result("carol", "Asia/Tokyo", "2025-03-14 22:00:00 UTC"),
+        ];
+
+        assert_eq!(
+            deltas(&daily_deltas(&results, &rules)),
+            [(
+                day(14),
+                vec![("alice", 200), ("bob", 500), ("carol", 50_400_000)]
+            )]
+        );
+        assert_eq!(
+            opening_time(day(14), &results[1], &rules).unwrap(),
+            Paris.with_ymd_and_hms(2025, 3, 14, 9, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn channel_openings_skipped_by_the_clocks_do_not_exist() {
+        // Clocks go from 02:00 to 03:00 in Paris on the 30th of March 2025.
+        let rules = in_paris(time(2, 30));
+        let results = [result(
+            "alice",
+            "Europe/Paris",
+            "2025-03-30 01:00:00.200 UTC",
+        )];
+
+        assert!(matches!(
+            opening_time(day(30), &results[0], &rules),
+            Err(Error::Time(_))
+        ));
+        assert_eq!(deltas(&daily_deltas(&results, &rules)), [(day(30), vec![])]);
+    }
+
+    #[test]
+    fn repeated_channel_openings_are_the_earliest() {
+        // Clocks go back from 03:00 to 02:00 in Paris on the 26th of October 2025,
+        // so 02:30 is first 00:30 UTC and then 01:30 UTC.
+        let rules = in_paris(time(2, 30));
+        let results = [result(
+            "alice",
+            "Europe/Paris",
+            "2025-10-26 00:30:00.200 UTC",
+        )];
+        let october = NaiveDate::from_ymd_opt(2025, 10, 26).unwrap();
+
+        assert_eq!(
+            opening_time(october, &results[0], &rules)
+                .unwrap()
+                .with_timezone(&Utc),
+            Utc.with_ymd_and_hms(2025, 10, 26, 0, 30, 0).unwrap()
+        );
+        assert_eq!(
+            deltas(&daily_deltas(&results, &rules)),
+            [(october, vec![("alice", 200)])]
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Opening times recorded for the days of channels, which take precedence over the schedule.
+
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveTime};
+
+use crate::config::Config;
+use crate::database::CsvRecord;
+use crate::error::{Error, Result};
+use crate::query::{Query, rules};
+use crate::storage::Storage;
+
+/// Name and version of the rule the bot draws opening times with:
+/// an hour and a minute from the opening ranges, seeded with the day of the month.
+pub const SEEDED_RULE: &str = "seeded-v1";
+
+/// The opening time the bot drew for a day of a channel, with the rule that drew it.
+///
+/// The time is a wall clock time, which applies in the timezone of each player
+/// or in the channel timezone, depending on the opening mode of the rules.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Opening {
+    /// Channel of the opening, in any casing.
+    pub channel: String,
+    /// Game day of the opening.
+    pub day: NaiveDate,
+    /// Wall clock opening time.
+    pub time: NaiveTime,
+    /// Name of the rule the time was drawn with, like [`SEEDED_RULE`].
+    pub rule: String,
+}
+
+impl CsvRecord for Opening {
+    const HEADERS: &'static [&'static str] = &["channel", "day", "time", "rule"];
+
+    fn from_fields(fields: &[String]) -> std::result::Result<Self, String> {
+        let [channel, day, time, rule] = fields else {
+            return Err(format!("expected 4 fields, found {}", fields.len()));
+        };
+
+        Ok(Self {
+            channel: channel.clone(),
+            day: day.parse().map_err(|_| format!("invalid day {day}"))?,
+            time: time.parse().map_err(|_| format!("invalid time {time}"))?,
+            rule: rule.clone(),
+        })
+    }
+
+    fn to_fields(&self) -> Vec<String> {
+        vec![
+            self.channel.clone(),
+            self.day.to_string(),
+            self.time.to_string(),
+            self.rule.clone(),
+        ]
+    }
+}
+
+/// Record the opening time of a day of the channel of the query, drawn with rule
+/// or else with the rule of the channel schedule, and return it.
+///
+/// A day has a single opening time, so recording it again replaces it.
+pub fn record_opening(
+    db: &impl Storage,
+    config: &Config,
+    query: &Query,
+    day: NaiveDate,
+    time: NaiveTime,
+    rule: Option<String>,
+) -> Result<Opening> {
+    let channel = query
+        .channel
+        .clone()
+        .ok_or_else(|| Error::Query(String::from("A channel must be provided")))?;
+    let opening = Opening {
+        rule: match rule {
+            Some(rule) => rule,
+            None => String::from(rules(config, query)?.schedule.rule()),
+        },
+        channel,
+        day,
+        time,
+    };
+    let channel = opening.channel.to_lowercase();
+
+    db.update(&config.openings, opening.clone(), |o: &&Opening| {
+        o.channel.to_lowercase() == channel && o.day == day
+    })?;
+
+    Ok(opening)
+}
+
+/// Recorded opening times by channel and day, which take precedence over the seeded ones.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Openings(HashMap<(String, NaiveDate), NaiveTime>);
+
+impl Openings {
+    /// Index the openings by lowercase channel and day, the last one recorded for a day winning.
+    pub fn new(openings: Vec<Opening>) -> Self {
+        Self(
+            openings
+                .into_iter()
+                .map(|opening| ((opening.channel.to_lowercase(), opening.day), opening.time))
+                .collect(),
+        )
+    }
+
+    /// The opening time recorded for a day of a channel (in any casing), if any.
+    pub fn get(&self, channel: &str, day: NaiveDate) -> Option<NaiveTime> {
+        self.0.get(&(channel.to_lowercase(), day)).copied()
+    }
+
+    /// Number of days with a recorded opening time.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether no opening time is recorded.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
@@ -0,0 +1,536 @@
+//! Reports of the commands and their rendering as text, JSON, CSV or IRC messages.
+
+use chrono::{DateTime, NaiveDate, SecondsFormat, TimeZone};
+use serde::Serialize;
+use serde_json::Map;
+
+use crate::error::{Error, Result};
+
+/// Maximum length of an IRC message, including the trailing CR LF.
+const IRC_MESSAGE_BYTES: usize = 512;
+
+/// Room left for the `:nick!user@host ` prefix the server adds when relaying a message.
+const IRC_PREFIX_BYTES: usize = 100;
+
+/// Room left for the target of a message when the channel is not known (the usual CHANNELLEN).
+const IRC_TARGET_BYTES: usize = 50;
+
+/// Smallest room for the text of a message, targets leaving less being rejected.
+const IRC_MIN_TEXT_BYTES: usize = 64;
+
+/// The `PRIVMSG <target> :<text>\r\n` command around the text of a message, without the target.
+const IRC_COMMAND: &str = "PRIVMSG  :\r\n";
+
+/// Room for the text of a message when its target is not known.
+const IRC_TEXT_BYTES: usize =
+    IRC_MESSAGE_BYTES - IRC_PREFIX_BYTES - IRC_COMMAND.len() - IRC_TARGET_BYTES;
+
+/// Separator between the entries packed in one IRC line.
+const IRC_SEPARATOR: &str = " | ";
+
+const IRC_BOLD: char = '\x02';
+const IRC_COLOUR: char = '\x03';
+const IRC_RESET: char = '\x0f';
+
+/// mIRC colours of the gold, silver and bronze medals.
+const IRC_MEDALS: [&str; 3] = ["08", "15", "07"];
+
+/// Format of the reports printed by the commands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Lines for a terminal.
+    Text,
+    /// A JSON object with the fields and rows of the report.
+    Json,
+    /// The rows under a line of column names.
+    Csv,
+    /// Lines packed for IRC messages.
+    Irc,
+}
+
+impl Format {
+    /// Parse the name of a format as given on the command line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            "irc" => Some(Format::Irc),
+            _ => None,
+        }
+    }
+}
+
+/// A value of a report field or column, serialized to JSON as the value it holds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Value {
+    /// No value, null in JSON and empty in CSV.
+    Null,
+    /// A flag.
+    Boolean(bool),
+    /// A count, position or delta.
+    Integer(i64),
+    /// Any other value, like a nick, date or datetime.
+    String(String),
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Boolean(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Value::Integer(value as i64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(String::from(value))
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<NaiveDate> for Value {
+    fn from(value: NaiveDate) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+/// Instants are written in RFC 3339, with microseconds and the offset of their timezone.
+impl<Tz: TimeZone> From<DateTime<Tz>> for Value
+where
+    Tz::Offset: std::fmt::Display,
+{
+    fn from(value: DateTime<Tz>) -> Self {
+        Value::String(value.to_rfc3339_opts(SecondsFormat::Micros, true))
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+/// The output of a command, kept as data so it can be rendered in any format.
+///
+/// The text format prints the title and the lines, which are written for people,
+/// while the JSON and CSV formats use the fields and the rows under the stable column names.
+#[derive(Debug)]
+pub struct Report {
+    kind: &'static str,
+    title: String,
+    fields: Vec<(&'static str, Value)>,
+    columns: Vec<&'static str>,
+    rows: Vec<Vec<Value>>,
+    /// Lines of the text format, with the position of the line if it is a place in a ranking.
+    lines: Vec<(Option<usize>, String)>,
+}
+
+impl Report {
+    /// Start a report of a kind (named in JSON), with the title of the text format
+    /// and the columns of its rows.
+    pub fn new(kind: &'static str, title: &str, columns: &[&'static str]) -> Self {
+        Self {
+            kind,
+            title: String::from(title),
+            fields: Vec::new(),
+            columns: columns.to_vec(),
+            rows: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    /// Add a field describing the whole report (JSON only).
+    pub fn field(&mut self, name: &'static str, value: impl Into<Value>) {
+        self.fields.push((name, value.into()));
+    }
+
+    /// Add a row, with one value per column.
+    pub fn row(&mut self, values: Vec<Value>) {
+        debug_assert_eq!(values.len(), self.columns.len());
+
+        self.rows.push(values);
+    }
+
+    /// Add a line of the text format.
+    pub fn line(&mut self, line: String) {
+        self.lines.push((None, line));
+    }
+
+    /// Add a line of the text format for a place (starting at 1) in a ranking.
+    pub fn ranked_line(&mut self, position: usize, line: String) {
+        self.lines.push((Some(position), line));
+    }
+
+    /// Render the report in a format, IRC messages having no target and no colours.
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Text => self.text(),
+            Format::Json => self.json(),
+            Format::Csv => self.csv(),
+            Format::Irc => self.irc_messages(IRC_TEXT_BYTES, false),
+        }
+    }
+
+    /// Pack the title and lines into as few IRC messages to target as possible, one per line.
+    ///
+    /// Each message fits the 512 bytes limit of IRC once the server adds its prefix,
+    /// and targets too long to leave room for the text of a message are rejected.
+    /// With colours, the first three places of a ranking are shown in bold medal colours.
+    pub fn irc(&self, target: Option<&str>, colours: bool) -> Result<String> {
+        let budget = match target {
+            None => IRC_TEXT_BYTES,
+            Some(target) => IRC_MESSAGE_BYTES
+                .checked_sub(IRC_PREFIX_BYTES + IRC_COMMAND.len() + target.len())
+                .filter(|budget| *budget >= IRC_MIN_TEXT_BYTES)
+                .ok_or_else(|| {
+                    Error::Query(format!("Target {target} is too long for IRC messages"))
+                })?,
+        };
+
+        Ok(self.irc_messages(budget, colours))
+    }
+
+    /// Pack the title and lines into messages of at most budget bytes of text.
+    fn irc_messages(&self, budget: usize, colours: bool) -> String {
+        let title = truncate(self.title.trim_end_matches(':'), budget);
+        let entries = self.lines.iter().map(|(position, line)| match position {
+            Some(position @ 1..=3) if colours => {
+                let medal = IRC_MEDALS[position - 1];
+                // Lines are cut before the codes are added, so an entry cut short still resets its colour.
+                let codes = IRC_BOLD.len_utf8()
+                    + IRC_COLOUR.len_utf8()
+                    + medal.len()
+                    + IRC_RESET.len_utf8();
+
+                format!(
+                    "{IRC_BOLD}{IRC_COLOUR}{medal}{}{IRC_RESET}",
+                    truncate(line, budget - codes)
+                )
+            }
+            _ => String::from(truncate(line, budget)),
+        });
+
+        let mut messages: Vec<String> = Vec::new();
+        let mut message = String::new();
+
+        for entry in std::iter::once(String::from(title))
+            .filter(|title| !title.is_empty())
+            .chain(entries)
+        {
+            let separator = match message.is_empty() {
+                true => "",
+                false => IRC_SEPARATOR,
+            };
+
+            if message.len() + separator.len() + entry.len() > budget {
+                messages.push(std::mem::take(&mut message));
+                message.push_str(&entry);
+            } else {
+                message.push_str(separator);
+                message.push_str(&entry);
+            }
+        }
+
+        if !message.is_empty() {
+            messages.push(message);
+        }
+
+        messages.into_iter().map(|message| message + "\n").collect()
+    }
+
+    fn text(&self) -> String {
+        let mut text = String::new();
+
+        if !self.title.is_empty() {
+            text.push_str(&self.title);
+            text.push('\n');
+        }
+
+        for (_position, line) in &self.lines {
+            text.push_str(line);
+            text.push('\n');
+        }
+
+        text
+    }
+
+    fn json(&self) -> String {
+        let entries: Vec<serde_json::Value> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let entry: Map<String, serde_json::Value> = self
+                    .columns
+                    .iter()
+                    .zip(row)
+                    .map(|(name, value)| (name.to_string(), json_value(value)))
+                    .collect();
+
+                entry.into()
+            })
+            .collect();
+        let mut json = Map::new();
+
+        json.insert(String::from("report"), self.kind.into());
+        json.extend(
+            self.fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), json_value(value))),
+        );
+        json.insert(String::from("entries"), entries.into());
+
+        serde_json::Value::Object(json).to_string() + "\n"
+    }
+
+    fn csv(&self) -> String {
+        let mut wtr = csv::WriterBuilder::new().from_writer(Vec::new());
+        let records = std::iter::once(self.columns.iter().map(|name| name.to_string()).collect())
+            .chain(self.rows.iter().map(|row| {
+                row.iter()
+                    .map(|value| match value {
+                        Value::Null => String::new(),
+                        Value::Boolean(value) => value.to_string(),
+                        Value::Integer(value) => value.to_string(),
+                        Value::String(value) => value.clone(),
+                    })
+                    .collect::<Vec<String>>()
+            }));
+
+        for record in records {
+            // Writing to memory cannot fail.
+            let _ = wtr.write_record(&record);
+        }
+
+        wtr.into_inner()
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .unwrap_or_default()
+    }
+}
+
+/// Cut a string to at most max bytes, on a character boundary.
+fn truncate(value: &str, max: usize) -> &str {
+    let mut end = value.len().min(max);
+
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    &value[..end]
+}
+
+fn json_value(value: &Value) -> serde_json::Value {
+    // Values are null, booleans, integers or strings, which always serialize.
+    serde_json::to_value(value).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: &str = "#first";
+
+    /// Room for the text of a message to TARGET.
+    fn budget() -> usize {
+        IRC_MESSAGE_BYTES - IRC_PREFIX_BYTES - IRC_COMMAND.len() - TARGET.len()
+    }
+
+    fn ranking(lines: &[String]) -> Report {
+        let mut report = Report::new("leaderboard", "", &[]);
+
+        for (index, line) in lines.iter().enumerate() {
+            report.ranked_line(index + 1, line.clone());
+        }
+
+        report
+    }
+
+    fn messages(report: &Report, colours: bool) -> Vec<String> {
+        report
+            .irc(Some(TARGET), colours)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn messages_fit_the_irc_limit() {
+        let lines: Vec<String> = (1..=40)
+            .map(|place| format!("{place}. {}", "é".repeat(place * 7)))
+            .collect();
+
+        for colours in [false, true] {
+            let messages = messages(&ranking(&lines), colours);
+
+            assert!(messages.len() > 1);
+            assert!(messages.iter().all(|message| message.len() <= budget()));
+        }
+    }
+
+    #[test]
+    fn entries_are_packed_greedily() {
+        let lines: Vec<String> = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|letter| letter.repeat(150))
+            .collect();
+        let mut report = Report::new("history", "Title:", &[]);
+
+        for line in &lines {
+            report.line(line.clone());
+        }
+
+        assert_eq!(
+            messages(&report, false),
+            [
+                format!("Title | {} | {}", lines[0], lines[1]),
+                format!("{} | {}", lines[2], lines[3]),
+                lines[4].clone(),
+            ]
+        );
+    }
+
+    #[test]
+    fn colours_are_only_added_to_the_medal_places() {
+        let lines: Vec<String> = (1..=5).map(|place| format!("{place}. nick")).collect();
+        let mut report = ranking(&lines);
+
+        report.line(String::from("not ranked"));
+
+        assert_eq!(
+            messages(&report, true),
+            [[
+                "\x02\x03081. nick\x0f",
+                "\x02\x03152. nick\x0f",
+                "\x02\x03073. nick\x0f",
+                "4. nick",
+                "5. nick",
+                "not ranked",
+            ]
+            .join(IRC_SEPARATOR)]
+        );
+        assert_eq!(
+            messages(&report, false),
+            [[lines.join(IRC_SEPARATOR).as_str(), "not ranked"].join(IRC_SEPARATOR)]
+        );
+    }
+
+    #[test]
+    fn long_coloured_entries_keep_their_colour_reset() {
+        let messages = messages(&ranking(&[format!("1. {}", "x".repeat(1000))]), true);
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].len(), budget());
+        assert!(messages[0].starts_with("\x02\x03081. x"));
+        assert!(messages[0].ends_with("x\x0f"));
+    }
+
+    #[test]
+    fn targets_leaving_no_room_for_text_are_rejected() {
+        let report = ranking(&[String::from("1. nick")]);
+        let longest = "#".repeat(TARGET.len() + budget() - IRC_MIN_TEXT_BYTES);
+
+        assert_eq!(report.irc(Some(&longest), false).unwrap(), "1. nick\n");
+
+        for target in [format!("{longest}#"), "#".repeat(600)] {
+            assert!(matches!(
+                report.irc(Some(&target), false),
+                Err(Error::Query(message)) if message.ends_with("is too long for IRC messages")
+            ));
+        }
+    }
+
+    #[test]
+    fn messages_to_the_longest_targets_fit_the_irc_limit() {
+        let target = "#".repeat(TARGET.len() + budget() - IRC_MIN_TEXT_BYTES);
+        let lines: Vec<String> = (1..=10)
+            .map(|place| format!("{place}. {}", "x".repeat(100)))
+            .collect();
+
+        for colours in [false, true] {
+            let messages = ranking(&lines).irc(Some(&target), colours).unwrap();
+
+            assert!(messages.lines().all(|message| {
+                IRC_PREFIX_BYTES + IRC_COMMAND.len() + target.len() + message.len()
+                    <= IRC_MESSAGE_BYTES
+            }));
+        }
+    }
+
+    fn players(nicks: &[&str]) -> Report {
+        let mut report = Report::new("players", "Players:", &["nick", "firsts", "last"]);
+
+        report.field("channel", "#chan");
+        report.field("since", None::<String>);
+
+        for (firsts, nick) in (1_i64..).zip(nicks) {
+            report.row(vec![Value::from(*nick), Value::from(firsts), Value::Null]);
+        }
+
+        report
+    }
+
+    #[test]
+    fn json_reports_without_rows_have_no_entries() {
+        assert_eq!(
+            players(&[]).render(Format::Json),
+            "{\"report\":\"players\",\"channel\":\"#chan\",\"since\":null,\"entries\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn json_reports_keep_the_order_of_their_fields_and_columns() {
+        let mut report = players(&["alice", "bob"]);
+
+        report.field("complete", true);
+
+        assert_eq!(
+            report.render(Format::Json),
+            concat!(
+                r##"{"report":"players","channel":"#chan","since":null,"complete":true,"entries":["##,
+                r#"{"nick":"alice","firsts":1,"last":null},"#,
+                r#"{"nick":"bob","firsts":2,"last":null}"#,
+                "]}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        let report = players(&[
+            "plain",
+            "with,comma",
+            "with \"quotes\"",
+            "multi\nline",
+            "Zoë",
+        ]);
+
+        assert_eq!(
+            report.render(Format::Csv),
+            concat!(
+                "nick,firsts,last\n",
+                "plain,1,\n",
+                "\"with,comma\",2,\n",
+                "\"with \"\"quotes\"\"\",3,\n",
+                "\"multi\nline\",4,\n",
+                "Zoë,5,\n"
+            )
+        );
+    }
+}
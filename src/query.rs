@@ -0,0 +1,389 @@
+//! Options of a command selecting the results and shaping its report, and the selection of those results.
+
+use std::ops::{Bound, Range};
+
+use chrono::Utc;
+use chrono_tz::Tz;
+
+use crate::config::Config;
+use crate::database::CsvRecord;
+use crate::dates::{DateRange, parse_span, start_date};
+use crate::error::{Error, Result};
+use crate::openings::{Opening, Openings};
+use crate::output::Format;
+use crate::ranking::RankMode;
+use crate::rules::{OpeningMode, RuleOverrides, Rules};
+use crate::schedule::OpeningSchedule;
+use crate::storage::Storage;
+use crate::{FirstResult, Period};
+
+/// Options selecting and shaping the results a command works on,
+/// as given on the command line or by a bot command.
+///
+/// The limit, cutoff and opening options override the rules of the channel (see [`rules`]),
+/// and the period and date options bound the results (see [`date_range`]).
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    /// Channel of the results, in any casing, or all channels.
+    pub channel: Option<String>,
+    /// Period of time going back from now.
+    pub period: Option<Period>,
+    /// Maximum number of entries of the report, instead of the max_results rule.
+    pub limit: Option<usize>,
+    /// Largest valid delta in milliseconds, instead of the cutoff rule.
+    pub cutoff_ms: Option<i64>,
+    /// Range the opening hour is drawn from, instead of the open_hour rule.
+    pub open_hour: Option<Range<u32>>,
+    /// Range the opening minute is drawn from, instead of the open_min rule.
+    pub open_min: Option<Range<u32>>,
+    /// Schedule of the opening times, instead of the schedule rule.
+    pub schedule: Option<OpeningSchedule>,
+    /// Where the opening time applies, instead of the opening_mode rule.
+    pub opening_mode: Option<OpeningMode>,
+    /// Date expression the results start at (--from).
+    pub from: Option<String>,
+    /// Date expression the results end with, included (--to).
+    pub to: Option<String>,
+    /// Date expression the results end before (--until).
+    pub until: Option<String>,
+    /// Date expression the results are within (--in).
+    pub during: Option<String>,
+    /// Timezone of the period, dates and times shown, instead of the channel timezone,
+    /// which the opening time of the channel opening mode stays in.
+    pub timezone: Option<Tz>,
+    /// How the leaderboard is ordered, the fastest winners by default.
+    pub mode: Option<RankMode>,
+    /// Format of the report, text by default.
+    pub format: Option<Format>,
+    /// Colour the medal places of IRC messages.
+    pub colours: bool,
+    /// Fail on invalid rows instead of skipping them.
+    pub strict: bool,
+}
+
+/// The bounds of the results, from either the period or the date options of the query,
+/// with days starting in tz.
+///
+/// --from and --in start at the start of their expression, --to includes the whole of its
+/// expression (or the instant, for now), and --until excludes its expression.
+pub fn date_range(query: &Query, tz: Tz) -> Result<DateRange> {
+    let now = Utc::now();
+    let dated = [&query.from, &query.to, &query.until, &query.during]
+        .into_iter()
+        .any(|option| option.is_some());
+
+    if dated && query.period.is_some() {
+        return Err(Error::Query(String::from(
+            "A period cannot be combined with --from, --to, --until or --in",
+        )));
+    }
+
+    if query.during.is_some() && (query.from.is_some() || query.to.is_some()) {
+        return Err(Error::Query(String::from(
+            "--in cannot be combined with --from or --to",
+        )));
+    }
+
+    if query.to.is_some() && query.until.is_some() {
+        return Err(Error::Query(String::from(
+            "--to cannot be combined with --until",
+        )));
+    }
+
+    let span = |expression: &String| parse_span(expression, now, tz).map_err(Error::Query);
+
+    let mut range = DateRange::default();
+
+    if let Some(period) = query.period {
+        range.start = match start_date(period, now, tz) {
+            Some(start) => Bound::Included(start),
+            None => return Err(Error::Query(String::from("Invalid time span"))),
+        };
+    }
+
+    if let Some(during) = query.during.as_ref().map(span).transpose()? {
+        range.start = Bound::Included(during.start);
+        range.end = Bound::Excluded(during.end);
+    }
+    if let Some(from) = query.from.as_ref().map(span).transpose()? {
+        range.start = Bound::Included(from.start);
+    }
+    if let Some(to) = query.to.as_ref().map(span).transpose()? {
+        // An instant (now) is included, while a day, month or year is included up to its end.
+        range.end = match to.start == to.end {
+            true => Bound::Included(to.end),
+            false => Bound::Excluded(to.end),
+        };
+    }
+    if let Some(until) = query.until.as_ref().map(span).transpose()? {
+        range.end = Bound::Excluded(until.start);
+    }
+
+    Ok(range)
+}
+
+/// The timezone of the period, dates and times of the query: its own, or else the channel timezone.
+pub fn timezone(query: &Query, rules: &Rules) -> Tz {
+    query.timezone.unwrap_or(rules.timezone)
+}
+
+/// The rules of the query channel, from the config and the overrides of the query.
+pub fn rules(config: &Config, query: &Query) -> Result<Rules> {
+    let mut rules = config.rules(query.channel.as_deref());
+
+    RuleOverrides {
+        max_results: query.limit,
+        cutoff_us: query
+            .cutoff_ms
+            .map(|cutoff_ms| {
+                cutoff_ms
+                    .checked_mul(1000)
+                    .ok_or_else(|| Error::Query(format!("The cutoff {cutoff_ms} ms is too large")))
+            })
+            .transpose()?,
+        open_hour: query.open_hour.clone(),
+        open_min: query.open_min.clone(),
+        schedule: query.schedule.clone(),
+        opening_mode: query.opening_mode,
+        timezone: None,
+    }
+    .apply(&mut rules);
+
+    rules.validate().map_err(Error::Query)?;
+
+    Ok(rules)
+}
+
+/// The rules of the query channel, with the opening times recorded for it,
+/// and the errors of the invalid openings skipped unless the query is strict.
+pub fn game_rules(
+    db: &impl Storage,
+    config: &Config,
+    query: &Query,
+) -> Result<(Rules, Vec<Error>)> {
+    let mut rules = rules(config, query)?;
+    let channel = query.channel.as_deref().map(str::to_lowercase);
+    let (openings, warnings) = select(db, &config.openings, query.strict, |o: &Opening| {
+        channel
+            .as_ref()
+            .is_none_or(|channel| o.channel.to_lowercase() == *channel)
+    })?;
+
+    rules.openings = Openings::new(openings.unwrap_or_default());
+
+    Ok((rules, warnings))
+}
+
+/// Select the results of the channel and dates of the query, in chronological order,
+/// and the errors of the invalid rows skipped unless the query is strict.
+pub fn select_results(
+    db: &impl Storage,
+    config: &Config,
+    query: &Query,
+    channel_required: bool,
+) -> Result<(Vec<FirstResult>, Vec<Error>)> {
+    if channel_required && query.channel.is_none() {
+        return Err(Error::Query(String::from("A channel must be provided")));
+    }
+
+    let range = date_range(query, timezone(query, &rules(config, query)?))?;
+    let channel = query.channel.as_deref();
+    let (first_results, warnings) = match query.strict {
+        true => (
+            db.select_range(&config.collection, channel, &range)?,
+            Vec::new(),
+        ),
+        false => db.select_range_lenient(&config.collection, channel, &range)?,
+    };
+    let mut first_results = first_results.unwrap_or_default();
+
+    first_results.sort_by_key(|r| r.datetime);
+
+    Ok((first_results, warnings))
+}
+
+/// Select entities from a collection, either failing on its invalid rows (strict)
+/// or skipping them and returning their errors.
+pub fn select<T, P>(
+    db: &impl Storage,
+    collection: &str,
+    strict: bool,
+    where_filter: P,
+) -> Result<(Option<Vec<T>>, Vec<Error>)>
+where
+    T: CsvRecord,
+    P: FnMut(&T) -> bool,
+{
+    match strict {
+        true => Ok((db.select(collection, where_filter)?, Vec::new())),
+        false => db.select_lenient(collection, where_filter),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use chrono::{DateTime, TimeZone};
+
+    use super::*;
+    use crate::test_support::{ConfigBuilder, TempDir};
+
+    fn paris(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Tz::Europe__Paris
+            .with_ymd_and_hms(year, month, day, 0, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn dated(from: Option<&str>, to: Option<&str>, until: Option<&str>) -> Result<DateRange> {
+        date_range(
+            &Query {
+                from: from.map(String::from),
+                to: to.map(String::from),
+                until: until.map(String::from),
+                ..Query::default()
+            },
+            Tz::Europe__Paris,
+        )
+    }
+
+    #[test]
+    fn to_includes_its_whole_day_and_until_excludes_it() {
+        assert_eq!(
+            dated(Some("2025-03-01"), Some("2025-03-14"), None).unwrap(),
+            DateRange {
+                start: Bound::Included(paris(2025, 3, 1)),
+                end: Bound::Excluded(paris(2025, 3, 15)),
+            }
+        );
+        assert_eq!(
+            dated(Some("2025-03-01"), None, Some("2025-03-14")).unwrap(),
+            DateRange {
+                start: Bound::Included(paris(2025, 3, 1)),
+                end: Bound::Excluded(paris(2025, 3, 14)),
+            }
+        );
+    }
+
+    #[test]
+    fn to_includes_its_whole_month_and_year() {
+        assert_eq!(
+            dated(None, Some("2025-02"), None).unwrap().end,
+            Bound::Excluded(paris(2025, 3, 1))
+        );
+        assert_eq!(
+            dated(None, Some("2024"), None).unwrap().end,
+            Bound::Excluded(paris(2025, 1, 1))
+        );
+    }
+
+    #[test]
+    fn to_now_includes_the_instant() {
+        assert!(matches!(
+            dated(None, Some("now"), None).unwrap().end,
+            Bound::Included(_)
+        ));
+        assert!(matches!(
+            dated(None, None, Some("now")).unwrap().end,
+            Bound::Excluded(_)
+        ));
+    }
+
+    #[test]
+    fn in_spans_its_expression() {
+        let query = Query {
+            during: Some(String::from("2025-03")),
+            ..Query::default()
+        };
+
+        assert_eq!(
+            date_range(&query, Tz::Europe__Paris).unwrap(),
+            DateRange {
+                start: Bound::Included(paris(2025, 3, 1)),
+                end: Bound::Excluded(paris(2025, 4, 1)),
+            }
+        );
+    }
+
+    #[test]
+    fn conflicting_date_options_are_rejected() {
+        assert!(matches!(
+            dated(None, Some("2025-03-14"), Some("2025-03-14")),
+            Err(Error::Query(_))
+        ));
+        assert!(matches!(
+            date_range(
+                &Query {
+                    period: Some(Period::Week),
+                    from: Some(String::from("2025-03-01")),
+                    ..Query::default()
+                },
+                Tz::UTC
+            ),
+            Err(Error::Query(_))
+        ));
+        assert!(matches!(
+            date_range(
+                &Query {
+                    during: Some(String::from("2025-03")),
+                    to: Some(String::from("2025-03-14")),
+                    ..Query::default()
+                },
+                Tz::UTC
+            ),
+            Err(Error::Query(_))
+        ));
+    }
+
+    #[test]
+    fn query_timezone_does_not_move_the_channel_timezone() {
+        let config = ConfigBuilder::new(Path::new(""))
+            .with_rules(RuleOverrides {
+                timezone: Some(Tz::Europe__London),
+                ..RuleOverrides::default()
+            })
+            .build();
+        let query = Query {
+            timezone: Some(Tz::Asia__Tokyo),
+            ..Query::default()
+        };
+        let rules = rules(&config, &query).unwrap();
+
+        assert_eq!(rules.timezone, Tz::Europe__London);
+        assert_eq!(timezone(&query, &rules), Tz::Asia__Tokyo);
+        assert_eq!(timezone(&Query::default(), &rules), Tz::Europe__London);
+    }
+
+    #[test]
+    fn strict_queries_fail_on_invalid_rows_that_lenient_ones_skip() {
+        let dir = TempDir::new("query-strict");
+        fs::write(
+            dir.join("first_results.csv"),
+            "alice,#test,2025-03-14 08:00:00 UTC,UTC\n\
+             bob,#test,2025-03-15 08:00:00 UTC\n",
+        )
+        .unwrap();
+
+        let config = dir.config().build();
+        let db = config.database().unwrap();
+        let lenient = Query {
+            channel: Some(String::from("#test")),
+            ..Query::default()
+        };
+        let strict = Query {
+            strict: true,
+            ..lenient.clone()
+        };
+        let (results, warnings) = select_results(&db, &config, &lenient, true).unwrap();
+        let failed = select_results(&db, &config, &strict, true);
+
+        assert_eq!(results.len(), 1);
+        assert!(matches!(
+            warnings[..],
+            [Error::Record { line: 2, ref message, .. }] if message == "expected 4 fields, found 3"
+        ));
+        assert!(matches!(failed, Err(Error::Record { line: 2, .. })));
+    }
+}
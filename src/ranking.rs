@@ -0,0 +1,349 @@
+//! Leaderboards of the attempts of each day: fastest winners, personal bests and standings.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate};
+use chrono_tz::Tz;
+use itertools::Itertools;
+
+use crate::rules::Rules;
+
+/// Points given to the first, second, third, fourth and fifth valid attempts of a day.
+pub const POINTS: [i64; 5] = [10, 7, 5, 3, 1];
+
+/// Number of wins averaged by default in the average mode.
+pub const AVERAGE_WINS: usize = 5;
+
+/// How the players of a leaderboard are ordered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RankMode {
+    /// Fastest single winning reaction of each player, see [`crate::rank`].
+    Fastest,
+    /// Best valid reaction of each player, whether or not it won its day, see [`personal_bests`].
+    Bests,
+    /// Players scored over all their days, see [`standings`].
+    Standings(Scoring),
+}
+
+/// How the players of [`standings`] are scored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scoring {
+    /// Most daily wins.
+    Wins,
+    /// Lowest average of each player's best N winning deltas, for players with at least N wins.
+    Average(usize),
+    /// Most points, from the placings of each day.
+    Points,
+}
+
+impl RankMode {
+    /// Parse the name of a mode as given on the command line.
+    pub fn from_name(name: &str, over: Option<usize>) -> Option<Self> {
+        match name {
+            "fastest" => Some(RankMode::Fastest),
+            "wins" => Some(RankMode::Standings(Scoring::Wins)),
+            "average" => Some(RankMode::Standings(Scoring::Average(
+                over.unwrap_or(AVERAGE_WINS),
+            ))),
+            "points" => Some(RankMode::Standings(Scoring::Points)),
+            "bests" => Some(RankMode::Bests),
+            _ => None,
+        }
+    }
+
+    /// Name of the mode, as parsed by [`RankMode::from_name`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            RankMode::Fastest => "fastest",
+            RankMode::Standings(Scoring::Wins) => "wins",
+            RankMode::Standings(Scoring::Average(_)) => "average",
+            RankMode::Standings(Scoring::Points) => "points",
+            RankMode::Bests => "bests",
+        }
+    }
+}
+
+/// A player's !1st on a day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attempt {
+    /// Nick of the player, as written in the result.
+    pub nick: String,
+    /// Gap to the opening time in microseconds, negative for a jump start.
+    pub delta_us: i64,
+    /// Opening time of the day, in the timezone it was computed in.
+    pub opening: DateTime<Tz>,
+}
+
+/// A player's place in a leaderboard.
+///
+/// The score is the number of wins, the points or a delta in microseconds, depending on the scoring.
+#[derive(Debug, PartialEq)]
+pub struct Standing {
+    /// Nick of the player, as written in the first of their attempts.
+    pub nick: String,
+    /// Number of days the player won.
+    pub wins: usize,
+    /// Score the players are ordered by.
+    pub score: i64,
+}
+
+/// A reaction of a player on a day.
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    /// Game day of the reaction.
+    pub date: NaiveDate,
+    /// Nick of the player, as written in the result.
+    pub nick: String,
+    /// Gap to the opening time in microseconds.
+    pub delta_us: i64,
+    /// Opening time of the day, in the timezone it was computed in.
+    pub opening: DateTime<Tz>,
+}
+
+/// Compute the top rules.max_results personal bests from the deltas of every day.
+///
+/// Each player's best valid delta is found across all days, independently of who won each day,
+/// so a player who was second on a record day keeps that time. Nicks are compared case insensitively.
+pub fn personal_bests(days: &[(NaiveDate, Vec<Attempt>)], rules: &Rules) -> Vec<Record> {
+    let mut bests: HashMap<String, Record> = HashMap::new();
+
+    for (day, attempts) in days {
+        for attempt in attempts {
+            if attempt.delta_us <= 0 || attempt.delta_us > rules.cutoff_us {
+                continue;
+            }
+
+            let best = bests
+                .entry(attempt.nick.to_lowercase())
+                .or_insert_with(|| Record {
+                    date: *day,
+                    nick: attempt.nick.clone(),
+                    delta_us: attempt.delta_us,
+                    opening: attempt.opening,
+                });
+
+            if attempt.delta_us < best.delta_us {
+                best.date = *day;
+                best.delta_us = attempt.delta_us;
+                best.opening = attempt.opening;
+            }
+        }
+    }
+
+    bests
+        .into_values()
+        .sorted_by(|a, b| a.delta_us.cmp(&b.delta_us).then(a.date.cmp(&b.date)))
+        .take(rules.max_results)
+        .collect()
+}
+
+/// Compute a leaderboard of the top rules.max_results players from the deltas of every day.
+///
+/// The deltas of each day must be sorted by lowest, as the winner of a day is its lowest delta when valid.
+/// Nicks are compared case insensitively. Ties are broken by nick.
+pub fn standings(
+    days: &[(NaiveDate, Vec<Attempt>)],
+    rules: &Rules,
+    scoring: Scoring,
+) -> Vec<Standing> {
+    let valid = |micros: i64| micros > 0 && micros <= rules.cutoff_us;
+
+    // The winning deltas of each player, keyed by lowercase nick.
+    let mut wins: HashMap<String, (String, Vec<i64>)> = HashMap::new();
+    let mut points: HashMap<String, (String, i64)> = HashMap::new();
+
+    for (_day, attempts) in days {
+        if let Some(winner) = attempts.first()
+            && valid(winner.delta_us)
+        {
+            wins.entry(winner.nick.to_lowercase())
+                .or_insert_with(|| (winner.nick.clone(), Vec::new()))
+                .1
+                .push(winner.delta_us);
+        }
+
+        // Placings only count each player's best valid attempt of the day.
+        let placings = attempts
+            .iter()
+            .filter(|attempt| valid(attempt.delta_us))
+            .unique_by(|attempt| attempt.nick.to_lowercase());
+
+        for (attempt, value) in placings.zip(POINTS) {
+            points
+                .entry(attempt.nick.to_lowercase())
+                .or_insert_with(|| (attempt.nick.clone(), 0))
+                .1 += value;
+        }
+    }
+
+    let count = |key: &str| wins.get(key).map_or(0, |(_nick, deltas)| deltas.len());
+
+    let standings: Vec<Standing> = match scoring {
+        Scoring::Points => points
+            .iter()
+            .map(|(key, (nick, score))| Standing {
+                nick: nick.clone(),
+                wins: count(key),
+                score: *score,
+            })
+            .sorted_by(|a, b| b.score.cmp(&a.score).then(a.nick.cmp(&b.nick)))
+            .collect(),
+        Scoring::Wins => wins
+            .values()
+            .map(|(nick, deltas)| Standing {
+                nick: nick.clone(),
+                wins: deltas.len(),
+                score: deltas.len() as i64,
+            })
+            .sorted_by(|a, b| b.score.cmp(&a.score).then(a.nick.cmp(&b.nick)))
+            .collect(),
+        Scoring::Average(over) => wins
+            .values()
+            .filter(|(_nick, deltas)| over > 0 && deltas.len() >= over)
+            .map(|(nick, deltas)| Standing {
+                nick: nick.clone(),
+                wins: deltas.len(),
+                score: deltas.iter().sorted().take(over).sum::<i64>() / over as i64,
+            })
+            .sorted_by(|a, b| a.score.cmp(&b.score).then(a.nick.cmp(&b.nick)))
+            .collect(),
+    };
+
+    standings.into_iter().take(rules.max_results).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, day).unwrap()
+    }
+
+    fn attempt(nick: &str, delta_ms: i64) -> Attempt {
+        Attempt {
+            nick: String::from(nick),
+            delta_us: delta_ms * 1000,
+            opening: Tz::UTC.with_ymd_and_hms(2025, 3, 1, 8, 0, 0).unwrap(),
+        }
+    }
+
+    /// Days with their attempts sorted by lowest delta, as daily_deltas returns them.
+    fn days(days: Vec<Vec<Attempt>>) -> Vec<(NaiveDate, Vec<Attempt>)> {
+        days.into_iter()
+            .enumerate()
+            .map(|(index, attempts)| {
+                (
+                    day(index as u32 + 1),
+                    attempts
+                        .into_iter()
+                        .sorted_by_key(|attempt| attempt.delta_us)
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn standing(nick: &str, wins: usize, score: i64) -> Standing {
+        Standing {
+            nick: String::from(nick),
+            wins,
+            score,
+        }
+    }
+
+    #[test]
+    fn points_count_the_best_valid_attempt_of_each_player() {
+        let days = days(vec![
+            vec![
+                attempt("Alice", 100),
+                attempt("alice", 150),
+                attempt("bob", 200),
+                attempt("carol", -5),
+                attempt("dave", 2000),
+            ],
+            vec![attempt("bob", 50), attempt("ALICE", 80)],
+        ]);
+
+        // Carol's jump start leaves the first day without a winner, but not without placings.
+        assert_eq!(
+            standings(&days, &Rules::default(), Scoring::Points),
+            [standing("Alice", 0, 17), standing("bob", 1, 17)]
+        );
+    }
+
+    #[test]
+    fn averages_only_rank_players_with_enough_wins() {
+        let days = days(vec![
+            vec![attempt("alice", 100), attempt("carol", 150)],
+            vec![attempt("alice", 300)],
+            vec![attempt("Alice", 200)],
+            vec![attempt("bob", 50)],
+            vec![attempt("carol", 400)],
+            vec![attempt("carol", 500)],
+        ]);
+
+        assert_eq!(
+            standings(&days, &Rules::default(), Scoring::Average(2)),
+            [standing("alice", 3, 150_000), standing("carol", 2, 450_000)]
+        );
+        assert_eq!(standings(&days, &Rules::default(), Scoring::Average(4)), []);
+    }
+
+    #[test]
+    fn ties_are_broken_by_nick() {
+        let days = days(vec![
+            vec![attempt("carol", 100)],
+            vec![attempt("bob", 100)],
+            vec![attempt("alice", 100)],
+        ]);
+
+        assert_eq!(
+            standings(&days, &Rules::default(), Scoring::Wins),
+            [
+                standing("alice", 1, 1),
+                standing("bob", 1, 1),
+                standing("carol", 1, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn standings_keep_max_results_players() {
+        let days = days(vec![
+            vec![attempt("alice", 100), attempt("bob", 200)],
+            vec![attempt("alice", 100), attempt("carol", 200)],
+        ]);
+        let rules = Rules {
+            max_results: 2,
+            ..Rules::default()
+        };
+
+        assert_eq!(
+            standings(&days, &rules, Scoring::Points),
+            [standing("alice", 2, 20), standing("bob", 0, 7)]
+        );
+    }
+
+    #[test]
+    fn personal_bests_keep_times_that_did_not_win() {
+        let days = days(vec![
+            vec![attempt("alice", 100), attempt("bob", 120)],
+            vec![attempt("bob", 300), attempt("alice", 2000)],
+            vec![attempt("carol", -10)],
+        ]);
+
+        assert_eq!(
+            personal_bests(&days, &Rules::default())
+                .into_iter()
+                .map(|record| (record.nick, record.date, record.delta_us))
+                .collect::<Vec<_>>(),
+            [
+                (String::from("alice"), day(1), 100_000),
+                (String::from("bob"), day(1), 120_000)
+            ]
+        );
+    }
+}
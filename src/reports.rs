@@ -0,0 +1,899 @@
+//! Reports of the commands of first_top, built from a collection and a query
+//! and rendered by the caller in any [`Format`](crate::output::Format).
+//!
+//! The errors of the rows skipped while selecting (unless the query is strict) are added
+//! to the warnings passed in, even when the report itself fails.
+
+use chrono::{NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use itertools::Itertools;
+
+use crate::check::{Issue, Problem, Row, check};
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::output::{Report, Value};
+use crate::query::{Query, game_rules, select_results, timezone};
+use crate::ranking::{Attempt, RankMode, Scoring, personal_bests, standings};
+use crate::rules::OpeningMode;
+use crate::stats::player_stats;
+use crate::storage::Storage;
+use crate::{FirstResult, attempt, daily_deltas, daily_winners, day_opening, game_day, rank};
+
+/// The leaderboard of the channel and dates of the query, in its rank mode.
+pub fn rank_report(
+    db: &impl Storage,
+    config: &Config,
+    query: &Query,
+    warnings: &mut Vec<Error>,
+) -> Result<Report> {
+    let first_results = first_results(db, config, query, true, warnings)?;
+    let rules = warned(game_rules(db, config, query), warnings)?;
+    let mode = query.mode.unwrap_or(RankMode::Fastest);
+    let days = daily_deltas(&first_results, &rules);
+
+    let title = match mode {
+        RankMode::Fastest => {
+            String::from("Top !first results (smallest gaps to the opening time of winners):")
+        }
+        RankMode::Bests => String::from(
+            "Top !first personal bests (smallest gaps to the opening time of each player):",
+        ),
+        RankMode::Standings(Scoring::Wins) => String::from("Top !first players (most daily wins):"),
+        RankMode::Standings(Scoring::Average(over)) => {
+            format!("Top !first players (best average gap over {over} wins):")
+        }
+        RankMode::Standings(Scoring::Points) => {
+            String::from("Top !first players (most points from daily placings):")
+        }
+    };
+    let mut report = Report::new(
+        "leaderboard",
+        &title,
+        &[
+            "position",
+            "nick",
+            "date",
+            "delta_us",
+            "opening_time",
+            "wins",
+            "score",
+        ],
+    );
+
+    report.field("channel", query.channel.clone());
+    report.field("mode", mode.name());
+
+    match mode {
+        RankMode::Fastest => {
+            for (pos, (date, x)) in rank(&first_results, &rules)?.into_iter().enumerate() {
+                let date = date?;
+                let winner = x
+                    .first()
+                    .ok_or_else(|| Error::NoData(String::from("Could not get data")))?;
+
+                report.ranked_line(
+                    pos + 1,
+                    format!(
+                        "{}. {:?} {} {} ms",
+                        pos + 1,
+                        date,
+                        winner.nick,
+                        winner.delta_us / 1000
+                    ),
+                );
+                report.row(vec![
+                    (pos + 1).into(),
+                    winner.nick.as_str().into(),
+                    date.into(),
+                    winner.delta_us.into(),
+                    winner.opening.into(),
+                    Value::Null,
+                    Value::Null,
+                ]);
+            }
+        }
+        RankMode::Bests => {
+            for (pos, best) in personal_bests(&days, &rules).into_iter().enumerate() {
+                report.ranked_line(
+                    pos + 1,
+                    format!(
+                        "{}. {:?} {} {} ms",
+                        pos + 1,
+                        best.date,
+                        best.nick,
+                        best.delta_us / 1000
+                    ),
+                );
+                report.row(vec![
+                    (pos + 1).into(),
+                    best.nick.into(),
+                    best.date.into(),
+                    best.delta_us.into(),
+                    best.opening.into(),
+                    Value::Null,
+                    Value::Null,
+                ]);
+            }
+        }
+        RankMode::Standings(scoring) => {
+            for (pos, standing) in standings(&days, &rules, scoring).into_iter().enumerate() {
+                let line = match scoring {
+                    Scoring::Wins => {
+                        format!("{}. {} {} wins", pos + 1, standing.nick, standing.score)
+                    }
+                    Scoring::Average(_) => format!(
+                        "{}. {} {} ms ({} wins)",
+                        pos + 1,
+                        standing.nick,
+                        standing.score / 1000,
+                        standing.wins
+                    ),
+                    Scoring::Points => format!(
+                        "{}. {} {} points ({} wins)",
+                        pos + 1,
+                        standing.nick,
+                        standing.score,
+                        standing.wins
+                    ),
+                };
+
+                report.ranked_line(pos + 1, line);
+                report.row(vec![
+                    (pos + 1).into(),
+                    standing.nick.into(),
+                    Value::Null,
+                    match scoring {
+                        Scoring::Average(_) => standing.score.into(),
+                        Scoring::Wins | Scoring::Points => Value::Null,
+                    },
+                    Value::Null,
+                    standing.wins.into(),
+                    standing.score.into(),
+                ]);
+            }
+        }
+    }
+
+    if let RankMode::Standings(Scoring::Average(over)) = mode {
+        report.field("over", over);
+    }
+
+    Ok(report)
+}
+
+/// The attempts, players, game days and days with a winner of the channel and dates of the query,
+/// with the wins and attempts of each player.
+pub fn stats_report(
+    db: &impl Storage,
+    config: &Config,
+    query: &Query,
+    warnings: &mut Vec<Error>,
+) -> Result<Report> {
+    let first_results = first_results(db, config, query, true, warnings)?;
+    let rules = warned(game_rules(db, config, query), warnings)?;
+    let winners = daily_winners(&first_results, &rules);
+
+    // Game days, as the days with a winner are.
+    let days = first_results
+        .iter()
+        .filter_map(|r| game_day(r, &rules))
+        .unique()
+        .count();
+    let players = first_results
+        .iter()
+        .into_group_map_by(|r| r.nick.to_lowercase());
+    let wins = winners
+        .iter()
+        .filter_map(|(_day, x)| x.first())
+        .counts_by(|winner| winner.nick.to_lowercase());
+
+    let mut report = Report::new(
+        "channel_stats",
+        "",
+        &["position", "nick", "wins", "attempts"],
+    );
+
+    report.field("channel", query.channel.clone());
+    report.field("attempts", first_results.len());
+    report.field("players", players.len());
+    report.field("days", days);
+    report.field("days_with_winner", winners.len());
+    report.line(format!(
+        "{} attempts by {} players over {} days, {} days with a winner.",
+        first_results.len(),
+        players.len(),
+        days,
+        winners.len()
+    ));
+
+    let mut rows: Vec<(&str, usize, usize)> = players
+        .iter()
+        .map(|(key, results)| {
+            (
+                results[0].nick.as_str(),
+                results.len(),
+                wins.get(key).copied().unwrap_or_default(),
+            )
+        })
+        .collect();
+
+    rows.sort_by(|a, b| b.2.cmp(&a.2).then(b.1.cmp(&a.1)).then(a.0.cmp(b.0)));
+
+    for (pos, (nick, attempts, wins)) in rows
+        .into_iter()
+        .take(query.limit.unwrap_or(usize::MAX))
+        .enumerate()
+    {
+        report.ranked_line(
+            pos + 1,
+            format!(
+                "{}. {} {} wins in {} attempts",
+                pos + 1,
+                nick,
+                wins,
+                attempts
+            ),
+        );
+        report.row(vec![
+            (pos + 1).into(),
+            nick.into(),
+            wins.into(),
+            attempts.into(),
+        ]);
+    }
+
+    Ok(report)
+}
+
+/// The stats of a player over the channel and dates of the query, see [`player_stats`].
+pub fn player_stats_report(
+    db: &impl Storage,
+    config: &Config,
+    query: &Query,
+    nick: &str,
+    warnings: &mut Vec<Error>,
+) -> Result<Report> {
+    let first_results = first_results(db, config, query, true, warnings)?;
+    let rules = warned(game_rules(db, config, query), warnings)?;
+    let days = daily_deltas(&first_results, &rules);
+
+    let stats = match player_stats(nick, &days, &rules) {
+        Some(stats) => stats,
+        None => return Err(Error::NoData(format!("No results found for {nick}"))),
+    };
+    let ms = |micros: Option<i64>| match micros {
+        Some(micros) => format!("{} ms", micros / 1000),
+        None => String::from("-"),
+    };
+
+    let mut report = Report::new(
+        "player_stats",
+        &format!("Stats of {}:", stats.nick),
+        &["month", "attempts", "wins", "best_us"],
+    );
+
+    report.field("channel", query.channel.clone());
+    report.field("nick", stats.nick.as_str());
+    report.field("attempts", stats.attempts);
+    report.field("wins", stats.wins);
+    report.field("valid", stats.valid);
+    report.field("best_us", stats.best_us);
+    report.field("median_us", stats.median_us);
+    report.field("mean_us", stats.mean_us);
+    report.field("longest_streak", stats.longest_streak);
+    report.field("current_streak", stats.current_streak);
+    report.field("early", stats.early);
+    report.field("late", stats.late);
+
+    report.line(format!(
+        "{} wins in {} attempts",
+        stats.wins, stats.attempts
+    ));
+    report.line(format!(
+        "Best {}, median {}, mean {} over {} valid attempts",
+        ms(stats.best_us),
+        ms(stats.median_us),
+        ms(stats.mean_us),
+        stats.valid
+    ));
+    report.line(format!(
+        "Longest streak {} days, current streak {} days",
+        stats.longest_streak, stats.current_streak
+    ));
+    report.line(format!(
+        "{} attempts before the opening, {} after the cutoff",
+        stats.early, stats.late
+    ));
+    report.line(String::from("Per month:"));
+
+    for month in &stats.months {
+        let name = format!("{}-{:02}", month.year, month.month);
+
+        report.line(format!(
+            "{} {} wins in {} attempts, best {}",
+            name,
+            month.wins,
+            month.attempts,
+            ms(month.best_us)
+        ));
+        report.row(vec![
+            name.into(),
+            month.attempts.into(),
+            month.wins.into(),
+            month.best_us.into(),
+        ]);
+    }
+
+    Ok(report)
+}
+
+/// The winner of each day of the channel and dates of the query, most recent first.
+pub fn history_report(
+    db: &impl Storage,
+    config: &Config,
+    query: &Query,
+    warnings: &mut Vec<Error>,
+) -> Result<Report> {
+    let first_results = first_results(db, config, query, true, warnings)?;
+    let winners = daily_winners(
+        &first_results,
+        &warned(game_rules(db, config, query), warnings)?,
+    );
+
+    let mut report = Report::new(
+        "history",
+        "Daily winners, most recent first:",
+        &["date", "nick", "delta_us", "opening_time"],
+    );
+
+    report.field("channel", query.channel.clone());
+
+    for (date, x) in winners
+        .into_iter()
+        .rev()
+        .take(query.limit.unwrap_or(usize::MAX))
+    {
+        let date = date?;
+        let winner = x
+            .first()
+            .ok_or_else(|| Error::NoData(String::from("Could not get data")))?;
+
+        report.line(format!(
+            "{:?} {} {} ms",
+            date,
+            winner.nick,
+            winner.delta_us / 1000
+        ));
+        report.row(vec![
+            date.into(),
+            winner.nick.as_str().into(),
+            winner.delta_us.into(),
+            winner.opening.into(),
+        ]);
+    }
+
+    Ok(report)
+}
+
+/// The opening times of the channel of the query from today to days ahead, shown in the query timezone.
+///
+/// Days whose opening time the clocks skip are listed without one, as there is no game on them.
+pub fn schedule_report(
+    db: &impl Storage,
+    config: &Config,
+    query: &Query,
+    days: usize,
+    warnings: &mut Vec<Error>,
+) -> Result<Report> {
+    let rules = warned(game_rules(db, config, query), warnings)?;
+    let tz = timezone(query, &rules);
+    // The opening time is a wall clock time in the channel timezone with the channel opening mode,
+    // and in the timezone of each player otherwise, where a player in tz sees it as it is.
+    let opening_tz = match rules.opening_mode {
+        OpeningMode::Channel => rules.timezone,
+        OpeningMode::Player => tz,
+    };
+    let today = Utc::now().with_timezone(&opening_tz).date_naive();
+    let channel = query.channel.as_deref().unwrap_or_default();
+
+    let mut report = Report::new(
+        "schedule",
+        &format!("Opening times in {tz} ({} schedule):", rules.schedule),
+        &["date", "opening_time", "recorded"],
+    );
+
+    report.field("channel", query.channel.clone());
+    report.field("schedule", rules.schedule.to_string());
+    report.field("opening_mode", rules.opening_mode.name());
+
+    for day in today.iter_days().take(days + 1) {
+        let time = day_opening(day, channel, &rules)?;
+        let recorded = rules.openings.get(channel, day).is_some();
+        // The earliest instant of the wall clock time when clocks go back, shown in tz.
+        let Some(opening) = opening_tz
+            .from_local_datetime(&day.and_time(time))
+            .earliest()
+            .map(|opening| opening.with_timezone(&tz))
+        else {
+            report.line(format!(
+                "{} {} does not exist in {opening_tz}{}",
+                day.format("%a %Y-%m-%d"),
+                time.format("%H:%M"),
+                match recorded {
+                    true => " (recorded)",
+                    false => "",
+                }
+            ));
+            report.row(vec![day.into(), Value::Null, recorded.into()]);
+            continue;
+        };
+
+        report.line(format!(
+            "{} {}{}",
+            day.format("%a %Y-%m-%d"),
+            opening.format("%H:%M %Z"),
+            match recorded {
+                true => " (recorded)",
+                false => "",
+            }
+        ));
+        report.row(vec![day.into(), opening.into(), recorded.into()]);
+    }
+
+    Ok(report)
+}
+
+/// All the attempts of a game day of the channel of the query, in order of their gap to the opening time.
+pub fn day_report(
+    db: &impl Storage,
+    config: &Config,
+    query: &Query,
+    day: NaiveDate,
+    warnings: &mut Vec<Error>,
+) -> Result<Report> {
+    let rules = warned(game_rules(db, config, query), warnings)?;
+    let invalid_date = || Error::Query(format!("Invalid date {day}"));
+
+    // Players are in any timezone, so look a day around the date and keep their game day.
+    let around = Query {
+        from: Some(day.pred_opt().ok_or_else(invalid_date)?.to_string()),
+        to: Some(day.succ_opt().ok_or_else(invalid_date)?.to_string()),
+        until: None,
+        during: None,
+        period: None,
+        timezone: Some(Tz::UTC),
+        ..query.clone()
+    };
+    let first_results: Vec<FirstResult> = first_results(db, config, &around, true, warnings)?
+        .into_iter()
+        .filter(|r| game_day(r, &rules) == Some(day))
+        .collect();
+
+    if first_results.is_empty() {
+        return Err(Error::NoData(String::from("No results found")));
+    }
+
+    // Attempts without an opening time (in a DST gap or an unknown timezone) are skipped,
+    // as they are when ranking.
+    let attempts: Vec<(Attempt, &FirstResult)> = first_results
+        .iter()
+        .filter_map(|r| Some((attempt(day, r, &rules).ok()?, r)))
+        .sorted_by_key(|(attempt, _r)| attempt.delta_us)
+        .collect();
+
+    let mut report = Report::new(
+        "day",
+        &format!("All !first attempts on {day}, in order of the gap to the opening time:"),
+        &[
+            "position",
+            "nick",
+            "played_at",
+            "opening_time",
+            "delta_us",
+            "jump_start",
+            "over_cutoff",
+        ],
+    );
+
+    report.field("channel", query.channel.clone());
+    report.field("date", day);
+
+    for (pos, (attempt, r)) in attempts.into_iter().enumerate() {
+        let jump_start = attempt.delta_us <= 0;
+        let over_cutoff = attempt.delta_us > rules.cutoff_us;
+        let played_at = r.datetime.with_timezone(&attempt.opening.timezone());
+
+        report.ranked_line(
+            pos + 1,
+            format!(
+                "{}. {} {:+} ms, played {} opening {}{}",
+                pos + 1,
+                attempt.nick,
+                attempt.delta_us / 1000,
+                played_at.format("%H:%M:%S%.3f"),
+                attempt.opening.format("%H:%M %Z"),
+                match (jump_start, over_cutoff) {
+                    (true, _) => " (jump start)",
+                    (_, true) => " (over the cutoff)",
+                    _ => "",
+                }
+            ),
+        );
+        report.row(vec![
+            (pos + 1).into(),
+            attempt.nick.into(),
+            played_at.into(),
+            attempt.opening.into(),
+            attempt.delta_us.into(),
+            jump_start.into(),
+            over_cutoff.into(),
+        ]);
+    }
+
+    Ok(report)
+}
+
+/// Check the rows of the collection, and remove the problematic ones if fix,
+/// returning the report with the number of problems left in the collection.
+///
+/// The collection is only rewritten when there is something to fix, checking it again under
+/// the same lock so the rows fixed are the rows checked. Rows that cannot be read are reported
+/// but never fixed, as their content would be lost: the collection is left as it is.
+pub fn check_collection(db: &impl Storage, config: &Config, fix: bool) -> Result<(Report, usize)> {
+    let now = Utc::now();
+    let (rows, skipped) = db.select_lenient(&config.collection, |_: &Row| true)?;
+
+    if rows.is_none() && skipped.is_empty() {
+        return Err(Error::NoData(String::from("No results found")));
+    }
+
+    let rows = rows.unwrap_or_default();
+    let mut issues = unreadable(skipped)?;
+    let unreadable = issues.len();
+
+    issues.extend(check(&rows, now));
+
+    if !fix || issues.is_empty() || unreadable > 0 {
+        let mut report = check_report(config, &rows, &issues, false);
+
+        if fix && unreadable > 0 {
+            report.line(format!(
+                "Not fixed: {unreadable} rows cannot be read and have to be fixed by hand"
+            ));
+        }
+
+        return Ok((report, issues.len()));
+    }
+
+    let mut report = None;
+
+    db.rewrite(&config.collection, |rows: Vec<Row>| {
+        // A collection emptied since it was checked is left as it is, rather than written empty.
+        if rows.is_empty() {
+            return Err(Error::NoData(String::from("No results found")));
+        }
+
+        let issues = check(&rows, now);
+        let mut fixed = check_report(config, &rows, &issues, fix);
+        let (keep, removed) = crate::check::fix(rows, &issues);
+
+        fixed.line(format!(
+            "Fixed: kept {} rows and removed {} rows",
+            keep.len(),
+            removed.len()
+        ));
+
+        // Removed rows are listed so nothing is lost without a trace.
+        for row in &removed {
+            fixed.line(format!("removed: {}", row.fields.join(",")));
+        }
+
+        report = Some(fixed);
+
+        Ok(keep)
+    })?;
+
+    report
+        .map(|report| (report, 0))
+        .ok_or_else(|| Error::NoData(String::from("No results found")))
+}
+
+/// The rows skipped for being unreadable, as problems at their line, passing on other errors.
+fn unreadable(skipped: Vec<Error>) -> Result<Vec<Issue>> {
+    skipped
+        .into_iter()
+        .map(|error| {
+            let (line, message) = match error {
+                Error::Csv { line, source, .. } => match source.kind() {
+                    csv::ErrorKind::Utf8 { err, .. } => (line, err.to_string()),
+                    _ => (line, String::from("invalid CSV")),
+                },
+                Error::Record { line, message, .. } => (Some(line), message),
+                Error::Table {
+                    rowid: Some(rowid),
+                    message,
+                    ..
+                } => (None, format!("row id {rowid}: {message}")),
+                error => return Err(error),
+            };
+
+            Ok(Issue {
+                row: None,
+                problem: Problem::Unreadable { line, message },
+            })
+        })
+        .collect()
+}
+
+/// The problems found in the rows of the collection, see [`check`].
+pub fn check_report(config: &Config, rows: &[Row], issues: &[Issue], fix: bool) -> Report {
+    let mut report = Report::new(
+        "check",
+        &format!(
+            "Checked {} rows of {}: {} problems",
+            rows.len(),
+            config.collection,
+            issues.len()
+        ),
+        &["row", "problem", "message"],
+    );
+
+    report.field("collection", config.collection.as_str());
+    report.field("rows", rows.len());
+    report.field("fixed", fix);
+
+    for issue in issues {
+        match issue.row {
+            Some(row) => report.line(format!("row {row}: {}", issue.problem)),
+            None => report.line(issue.problem.to_string()),
+        }
+        report.row(vec![
+            issue.row.into(),
+            issue.problem.name().into(),
+            issue.problem.to_string().into(),
+        ]);
+    }
+
+    report
+}
+
+/// Select the results of the query, failing if there are none.
+pub fn first_results(
+    db: &impl Storage,
+    config: &Config,
+    query: &Query,
+    channel_required: bool,
+    warnings: &mut Vec<Error>,
+) -> Result<Vec<FirstResult>> {
+    let first_results = warned(
+        select_results(db, config, query, channel_required),
+        warnings,
+    )?;
+
+    match first_results.is_empty() {
+        true => Err(Error::NoData(String::from("No results found"))),
+        false => Ok(first_results),
+    }
+}
+
+/// Add the errors of the rows skipped by a lenient select to warnings, passing on what was selected.
+pub fn warned<T>(selected: Result<(T, Vec<Error>)>, warnings: &mut Vec<Error>) -> Result<T> {
+    let (selected, skipped) = selected?;
+
+    warnings.extend(skipped);
+
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::output::Format;
+    use crate::test_support::{ConfigBuilder, TempDir};
+
+    /// The config of the tests, opening at 08:00 every day.
+    fn config(dir: &TempDir) -> ConfigBuilder {
+        dir.config().with_schedule("fixed 08:00")
+    }
+
+    fn results(dir: &TempDir) -> PathBuf {
+        dir.join("first_results.csv")
+    }
+
+    #[test]
+    fn leaderboards_are_built_in_process() {
+        let dir = TempDir::new("reports");
+        fs::write(
+            results(&dir),
+            "alice,#test,2025-03-14 08:00:00.300 UTC,UTC\n\
+             bob,#test,2025-03-14 08:00:00.500 UTC,UTC\n\
+             bob,#test,2025-03-15 08:00:00.200 UTC,UTC\n",
+        )
+        .unwrap();
+
+        let config = config(&dir).build();
+        let query = Query {
+            channel: Some(String::from("#test")),
+            ..Query::default()
+        };
+        let mut warnings = Vec::new();
+        let report = rank_report(&config.database().unwrap(), &config, &query, &mut warnings);
+
+        assert!(warnings.is_empty());
+        assert_eq!(
+            report.unwrap().irc(None, false).unwrap(),
+            "Top !first results (smallest gaps to the opening time of winners) | \
+             1. 2025-03-15 bob 200 ms | 2. 2025-03-14 alice 300 ms\n"
+        );
+    }
+
+    #[test]
+    fn fixed_collections_check_clean() {
+        let dir = TempDir::new("check-fix");
+        fs::write(
+            results(&dir),
+            "alice,#Test,2025-03-14 08:00:00.300 UTC,UTC\n\
+             alice,#test,2025-03-14 08:00:00.300 UTC,UTC\n\
+             bob,#test,2025-03-15 08:00:00.200 UTC,UTC\n",
+        )
+        .unwrap();
+
+        let config = config(&dir).build();
+        let db = config.database().unwrap();
+        let (report, left) = check_collection(&db, &config, true).unwrap();
+
+        assert!(
+            report
+                .render(Format::Text)
+                .starts_with("Checked 3 rows of first_results: 1 problems\n")
+        );
+        assert_eq!(left, 0);
+        assert_eq!(
+            fs::read_to_string(results(&dir)).unwrap(),
+            "alice,#test,2025-03-14 08:00:00.300 UTC,UTC\n\
+             bob,#test,2025-03-15 08:00:00.200 UTC,UTC\n"
+        );
+        assert_eq!(check_collection(&db, &config, false).unwrap().1, 0);
+    }
+
+    #[test]
+    fn fixing_a_clean_collection_leaves_it_as_it_is() {
+        let dir = TempDir::new("check-clean");
+        let rows = "alice,#test,2025-03-14 08:00:00.300 UTC,UTC\n";
+        fs::write(results(&dir), rows).unwrap();
+
+        let config = config(&dir).with_backups(1).build();
+        let (_report, left) = check_collection(&config.database().unwrap(), &config, true).unwrap();
+
+        assert_eq!(left, 0);
+        assert_eq!(fs::read_to_string(results(&dir)).unwrap(), rows);
+        assert!(!dir.join("first_results.csv.1").exists());
+    }
+
+    #[test]
+    fn unreadable_rows_are_reported_at_their_line_and_not_fixed() {
+        let dir = TempDir::new("check-unreadable");
+        let mut rows = b"#schema 1\nnick,channel,datetime,timezone\n".to_vec();
+        rows.extend(b"alice,#test,2025-03-14 08:00:00.300 UTC,UTC\n");
+        rows.extend(b"b\xffb,#test,2025-03-15 08:00:00.200 UTC,UTC\n");
+        rows.extend(b"alice,#test,2025-03-14 08:00:00.300 UTC,UTC\n");
+        fs::write(results(&dir), &rows).unwrap();
+
+        let config = config(&dir).build();
+        let db = config.database().unwrap();
+        let (report, left) = check_collection(&db, &config, false).unwrap();
+
+        assert_eq!(left, 2);
+        assert_eq!(
+            report.render(Format::Text),
+            "Checked 2 rows of first_results: 2 problems\n\
+             line 4 cannot be read: invalid utf-8: invalid UTF-8 in field 0 near byte index 1\n\
+             row 2: duplicate of row 1\n"
+        );
+
+        let (report, left) = check_collection(&db, &config, true).unwrap();
+
+        assert_eq!(left, 2);
+        assert!(
+            report
+                .render(Format::Text)
+                .ends_with("Not fixed: 1 rows cannot be read and have to be fixed by hand\n")
+        );
+        assert_eq!(fs::read(results(&dir)).unwrap(), rows);
+    }
+
+    #[test]
+    fn fixing_a_missing_collection_does_not_create_it() {
+        let dir = TempDir::new("check-missing");
+        let config = config(&dir).build();
+
+        assert!(matches!(
+            check_collection(&config.database().unwrap(), &config, true),
+            Err(Error::NoData(_))
+        ));
+        assert!(!results(&dir).exists());
+    }
+
+    #[test]
+    fn days_list_every_attempt_by_gap_to_the_opening() {
+        let dir = TempDir::new("day");
+        fs::write(
+            results(&dir),
+            "alice,#test,2025-03-14 07:59:59.900 UTC,UTC\n\
+             bob,#test,2025-03-14 08:00:00.250 UTC,UTC\n\
+             carol,#test,2025-03-14 08:00:02.500 UTC,UTC\n\
+             dave,#test,2025-03-15 08:00:00.100 UTC,UTC\n\
+             erin,#other,2025-03-14 08:00:00.050 UTC,UTC\n",
+        )
+        .unwrap();
+
+        let config = config(&dir).build();
+        let query = Query {
+            channel: Some(String::from("#test")),
+            ..Query::default()
+        };
+        let mut warnings = Vec::new();
+        let report = day_report(
+            &config.database().unwrap(),
+            &config,
+            &query,
+            NaiveDate::from_ymd_opt(2025, 3, 14).unwrap(),
+            &mut warnings,
+        );
+
+        assert!(warnings.is_empty());
+        assert_eq!(
+            report.unwrap().render(Format::Text),
+            "All !first attempts on 2025-03-14, in order of the gap to the opening time:\n\
+             1. alice -100 ms, played 07:59:59.900 opening 08:00 UTC (jump start)\n\
+             2. bob +250 ms, played 08:00:00.250 opening 08:00 UTC\n\
+             3. carol +2500 ms, played 08:00:02.500 opening 08:00 UTC (over the cutoff)\n"
+        );
+    }
+
+    #[test]
+    fn schedules_mark_the_openings_the_clocks_skip() {
+        let dir = TempDir::new("schedule-gap");
+        let config = dir.config().with_schedule("fixed 01:30").build();
+
+        let query = Query {
+            channel: Some(String::from("#test")),
+            timezone: Some(Tz::Europe__London),
+            ..Query::default()
+        };
+        let mut warnings = Vec::new();
+        // A year ahead has a spring forward, when 01:30 does not exist in London.
+        let report = schedule_report(
+            &config.database().unwrap(),
+            &config,
+            &query,
+            366,
+            &mut warnings,
+        )
+        .unwrap();
+        let text = report.render(Format::Text);
+        let skipped: Vec<&str> = text
+            .lines()
+            .filter(|line| line.ends_with("01:30 does not exist in Europe/London"))
+            .collect();
+
+        assert!(warnings.is_empty());
+        assert_eq!(text.lines().count(), 1 + 367);
+        assert!(!skipped.is_empty());
+        assert!(
+            skipped
+                .iter()
+                .all(|line| line.starts_with("Sun ") && line[8..].starts_with("-03-"))
+        );
+        assert_eq!(
+            report
+                .render(Format::Json)
+                .matches("\"opening_time\":null")
+                .count(),
+            skipped.len()
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! Rules of the game of a channel, from the config and the options of a query.
+
+use std::ops::Range;
+
+use chrono_tz::Tz;
+
+use crate::openings::Openings;
+use crate::schedule::OpeningSchedule;
+
+const MAX_RESULTS: usize = 10;
+const CUTOFF_US: i64 = 1000000;
+const RAND_OPEN_HOUR: Range<u32> = 5..12;
+const RAND_OPEN_MIN: Range<u32> = 0..59;
+
+/// Where the opening time of a day applies.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OpeningMode {
+    /// In the timezone of each player, as the bot does, so players in different timezones
+    /// race against different instants.
+    #[default]
+    Player,
+    /// In the channel timezone, the same instant for all the players.
+    Channel,
+}
+
+impl OpeningMode {
+    /// Parse the name of a mode as given on the command line or in the config file.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "player" => Some(OpeningMode::Player),
+            "channel" => Some(OpeningMode::Channel),
+            _ => None,
+        }
+    }
+
+    /// Name of the mode, as parsed by [`OpeningMode::from_name`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            OpeningMode::Player => "player",
+            OpeningMode::Channel => "channel",
+        }
+    }
+}
+
+/// Parameters of the game used to rank the results of a channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rules {
+    /// Maximum number of entries in the top list.
+    pub max_results: usize,
+    /// Reactions slower than this many microseconds after the opening are ignored.
+    pub cutoff_us: i64,
+    /// Range the bot draws the opening hour from.
+    pub open_hour: Range<u32>,
+    /// Range the bot draws the opening minute from.
+    pub open_min: Range<u32>,
+    /// How the bot picks the opening time of each day.
+    pub schedule: OpeningSchedule,
+    /// Whether the opening time applies in the timezone of each player or in the channel timezone.
+    pub opening_mode: OpeningMode,
+    /// Timezone of the channel, in which the opening time applies with [`OpeningMode::Channel`],
+    /// and in which periods and dates (today, this week, 2025-03) start and end unless a query sets another.
+    pub timezone: Tz,
+    /// Opening times recorded by the bot, used instead of drawing them again.
+    pub openings: Openings,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            max_results: MAX_RESULTS,
+            cutoff_us: CUTOFF_US,
+            open_hour: RAND_OPEN_HOUR,
+            open_min: RAND_OPEN_MIN,
+            schedule: OpeningSchedule::default(),
+            opening_mode: OpeningMode::default(),
+            timezone: Tz::UTC,
+            openings: Openings::default(),
+        }
+    }
+}
+
+impl Rules {
+    /// Check the rules can be used for ranking (the opening ranges must be valid and not empty).
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_results == 0 {
+            return Err(String::from(
+                "The maximum number of results must be positive",
+            ));
+        }
+
+        if self.cutoff_us <= 0 {
+            return Err(String::from("The cutoff must be positive"));
+        }
+
+        if self.open_hour.is_empty() || self.open_hour.end > 24 {
+            return Err(format!(
+                "Invalid opening hour range {}..{} (expected a non-empty range within 0..24)",
+                self.open_hour.start, self.open_hour.end
+            ));
+        }
+
+        if self.open_min.is_empty() || self.open_min.end > 60 {
+            return Err(format!(
+                "Invalid opening minute range {}..{} (expected a non-empty range within 0..60)",
+                self.open_min.start, self.open_min.end
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Rules set at one configuration layer, each replacing the value of the layers below when present.
+#[derive(Debug, Clone, Default)]
+pub struct RuleOverrides {
+    /// See [`Rules::max_results`].
+    pub max_results: Option<usize>,
+    /// See [`Rules::cutoff_us`].
+    pub cutoff_us: Option<i64>,
+    /// See [`Rules::open_hour`].
+    pub open_hour: Option<Range<u32>>,
+    /// See [`Rules::open_min`].
+    pub open_min: Option<Range<u32>>,
+    /// See [`Rules::schedule`].
+    pub schedule: Option<OpeningSchedule>,
+    /// See [`Rules::opening_mode`].
+    pub opening_mode: Option<OpeningMode>,
+    /// See [`Rules::timezone`].
+    pub timezone: Option<Tz>,
+}
+
+impl RuleOverrides {
+    /// Replace the rules that this layer sets.
+    pub fn apply(&self, rules: &mut Rules) {
+        if let Some(max_results) = self.max_results {
+            rules.max_results = max_results;
+        }
+        if let Some(cutoff_us) = self.cutoff_us {
+            rules.cutoff_us = cutoff_us;
+        }
+        if let Some(open_hour) = &self.open_hour {
+            rules.open_hour = open_hour.clone();
+        }
+        if let Some(open_min) = &self.open_min {
+            rules.open_min = open_min.clone();
+        }
+        if let Some(schedule) = &self.schedule {
+            rules.schedule = schedule.clone();
+        }
+        if let Some(opening_mode) = self.opening_mode {
+            rules.opening_mode = opening_mode;
+        }
+        if let Some(timezone) = self.timezone {
+            rules.timezone = timezone;
+        }
+    }
+}
+
+/// Parse a half-open range written as "start..end", like the ranges the bot draws from.
+pub fn parse_range(range: &str) -> Option<Range<u32>> {
+    let (start, end) = range.split_once("..")?;
+
+    Some(start.trim().parse().ok()?..end.trim().parse().ok()?)
+}
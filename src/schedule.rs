@@ -0,0 +1,244 @@
+//! Schedules of the opening time of each day.
+
+use std::fmt;
+use std::ops::Range;
+
+use chrono::{Datelike, NaiveDate, NaiveTime};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::openings::SEEDED_RULE;
+
+/// How the opening time of each day is chosen, which differs between the game variants of channels.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum OpeningSchedule {
+    /// An hour and a minute drawn from the opening ranges, seeded with the day of the month,
+    /// as the bot has always done.
+    #[default]
+    Seeded,
+    /// An hour and a minute drawn from the opening ranges, seeded with the whole date
+    /// (as the number YYYYMMDD), so the same day of different months opens at different times.
+    DateSeeded,
+    /// The same time every day.
+    Fixed(NaiveTime),
+    /// A time for each day of the week, from Monday to Sunday.
+    Weekdays([NaiveTime; 7]),
+}
+
+impl OpeningSchedule {
+    /// Parse a schedule as written in the config file or on the command line:
+    /// seeded, date-seeded, fixed HH:MM, or weekdays followed by the 7 times from Monday to Sunday.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut words = value.split_whitespace();
+        let invalid = || {
+            format!(
+                "Invalid schedule {value} (expected seeded, date-seeded, fixed HH:MM or weekdays with 7 times)"
+            )
+        };
+
+        let schedule = match words.next() {
+            Some("seeded") => OpeningSchedule::Seeded,
+            Some("date-seeded") => OpeningSchedule::DateSeeded,
+            Some("fixed") => OpeningSchedule::Fixed(parse_time(words.next().ok_or_else(invalid)?)?),
+            Some("weekdays") => {
+                let times = words
+                    .by_ref()
+                    .map(parse_time)
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                OpeningSchedule::Weekdays(times.try_into().map_err(|_| invalid())?)
+            }
+            _ => return Err(invalid()),
+        };
+
+        match words.next() {
+            Some(_) => Err(invalid()),
+            None => Ok(schedule),
+        }
+    }
+
+    /// Name and version of the rule, as recorded with the opening times it produces.
+    pub fn rule(&self) -> &'static str {
+        match self {
+            OpeningSchedule::Seeded => SEEDED_RULE,
+            OpeningSchedule::DateSeeded => "date-seeded-v1",
+            OpeningSchedule::Fixed(_) => "fixed",
+            OpeningSchedule::Weekdays(_) => "weekdays",
+        }
+    }
+
+    /// The wall clock opening time of a day, drawing from the opening ranges if the schedule is seeded.
+    ///
+    /// The ranges must not be empty, as checked by [`crate::rules::Rules::validate`].
+    pub fn opening(
+        &self,
+        day: NaiveDate,
+        open_hour: &Range<u32>,
+        open_min: &Range<u32>,
+    ) -> Option<NaiveTime> {
+        match self {
+            OpeningSchedule::Seeded => draw(day.day() as u64, open_hour, open_min),
+            OpeningSchedule::DateSeeded => {
+                let seed = day.year() as u64 * 10000 + day.month() as u64 * 100 + day.day() as u64;
+
+                draw(seed, open_hour, open_min)
+            }
+            OpeningSchedule::Fixed(time) => Some(*time),
+            OpeningSchedule::Weekdays(times) => {
+                Some(times[day.weekday().num_days_from_monday() as usize])
+            }
+        }
+    }
+}
+
+impl fmt::Display for OpeningSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OpeningSchedule::Seeded => write!(f, "seeded"),
+            OpeningSchedule::DateSeeded => write!(f, "date-seeded"),
+            OpeningSchedule::Fixed(time) => write!(f, "fixed {}", time.format("%H:%M")),
+            OpeningSchedule::Weekdays(times) => {
+                write!(f, "weekdays")?;
+
+                for time in times {
+                    write!(f, " {}", time.format("%H:%M"))?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Draw an opening hour and minute the way the bot does.
+fn draw(seed: u64, open_hour: &Range<u32>, open_min: &Range<u32>) -> Option<NaiveTime> {
+    // The bot draws the hour and the minute from two generators with the same seed.
+    let mut rng = StdRng::seed_from_u64(seed);
+    let hour = rng.random_range(open_hour.clone());
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let minute = rng.random_range(open_min.clone());
+
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .map_err(|_| format!("Invalid time {time} (expected HH:MM)"))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Timelike;
+
+    use super::*;
+
+    fn day(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, month, day).unwrap()
+    }
+
+    fn time(hour: u32, min: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, min, 0).unwrap()
+    }
+
+    /// The opening time of a day with the bot's opening ranges.
+    fn opening(schedule: &OpeningSchedule, day: NaiveDate) -> Option<NaiveTime> {
+        schedule.opening(day, &(5..12), &(0..59))
+    }
+
+    #[test]
+    fn seeded_openings_are_the_ones_the_bot_draws() {
+        // Drawn by the bot for these days, which any change to the generator or the seed breaks.
+        for (day, expected) in [
+            (day(3, 1), time(10, 48)),
+            (day(3, 14), time(10, 49)),
+            (day(3, 28), time(8, 25)),
+            (day(3, 31), time(6, 12)),
+            // Only the day of the month seeds the draw.
+            (day(7, 14), time(10, 49)),
+        ] {
+            assert_eq!(opening(&OpeningSchedule::Seeded, day), Some(expected));
+        }
+    }
+
+    #[test]
+    fn date_seeded_openings_differ_between_months() {
+        assert_eq!(
+            opening(&OpeningSchedule::DateSeeded, day(3, 1)),
+            Some(time(11, 53))
+        );
+        assert_eq!(
+            opening(&OpeningSchedule::DateSeeded, day(3, 28)),
+            Some(time(6, 14))
+        );
+        assert_ne!(
+            opening(&OpeningSchedule::DateSeeded, day(3, 1)),
+            opening(&OpeningSchedule::DateSeeded, day(4, 1))
+        );
+    }
+
+    #[test]
+    fn draws_stay_within_the_opening_ranges() {
+        for day in day(1, 1).iter_days().take(365) {
+            for schedule in [OpeningSchedule::Seeded, OpeningSchedule::DateSeeded] {
+                let opening = schedule.opening(day, &(6..8), &(10..20)).unwrap();
+
+                assert!((6..8).contains(&opening.hour()), "{schedule} {day}");
+                assert!((10..20).contains(&opening.minute()), "{schedule} {day}");
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_and_weekday_openings_ignore_the_ranges() {
+        let weekdays =
+            OpeningSchedule::parse("weekdays 08:00 08:30 09:00 09:30 10:00 10:30 11:00").unwrap();
+
+        assert_eq!(
+            opening(&OpeningSchedule::Fixed(time(13, 37)), day(3, 14)),
+            Some(time(13, 37))
+        );
+        // The 10th of March 2025 is a Monday.
+        assert_eq!(opening(&weekdays, day(3, 10)), Some(time(8, 0)));
+        assert_eq!(opening(&weekdays, day(3, 14)), Some(time(10, 0)));
+        assert_eq!(opening(&weekdays, day(3, 16)), Some(time(11, 0)));
+    }
+
+    #[test]
+    fn schedules_are_parsed_as_they_are_displayed() {
+        for spec in [
+            "seeded",
+            "date-seeded",
+            "fixed 09:05",
+            "weekdays 08:00 08:30 09:00 09:30 10:00 10:30 11:00",
+        ] {
+            assert_eq!(OpeningSchedule::parse(spec).unwrap().to_string(), spec);
+        }
+
+        assert_eq!(
+            OpeningSchedule::parse("  fixed   23:59 "),
+            Ok(OpeningSchedule::Fixed(time(23, 59)))
+        );
+    }
+
+    #[test]
+    fn invalid_schedules_are_rejected() {
+        for spec in [
+            "",
+            "random",
+            "seeded 09:00",
+            "fixed",
+            "fixed 9",
+            "fixed 24:00",
+            "fixed 09:00 10:00",
+            "weekdays 08:00 08:30 09:00",
+            "weekdays 08:00 08:30 09:00 09:30 10:00 10:30 11:00 11:30",
+        ] {
+            assert!(OpeningSchedule::parse(spec).is_err(), "{spec}");
+        }
+
+        assert_eq!(
+            OpeningSchedule::parse("fixed 25:00"),
+            Err(String::from("Invalid time 25:00 (expected HH:MM)"))
+        );
+    }
+}
@@ -0,0 +1,1132 @@
+//! Collections stored as tables of a SQLite database, with indexes for range queries.
+
+use std::collections::HashSet;
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{ErrorCode, OpenFlags, Row as SqlRow, params, params_from_iter};
+
+use crate::FirstResult;
+use crate::database::{CsvRecord, LOCK_TIMEOUT};
+use crate::dates::DateRange;
+use crate::error::{Error, Result};
+use crate::storage::{Storage, in_range, remove};
+
+/// Table holding the schema version of each collection.
+const SCHEMA_TABLE: &str = "first_top_schema";
+
+/// Column of the results that range selects compare.
+const DATETIME_COLUMN: &str = "datetime";
+
+/// Format of the stored datetimes: UTC with a fixed width, so that their text sorts in time order.
+const DATETIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.9fZ";
+
+/// Indexes of the collections that have their columns: name, columns and indexed expressions.
+const INDEXES: [(&str, &[&str], &str); 2] = [
+    (
+        "channel_datetime",
+        &["channel", "datetime"],
+        "lower(\"channel\"), \"datetime\"",
+    ),
+    ("datetime", &["datetime"], "\"datetime\""),
+];
+
+/// Collections stored as tables of a SQLite database, with one TEXT column per header of their records.
+///
+/// Rows keep the order they were written in. Datetimes are stored as sortable UTC text,
+/// and results are indexed by lowercase channel and datetime, so range selects are looked up in SQL.
+pub struct SqliteDatabase {
+    path: PathBuf,
+    lock_timeout: Duration,
+}
+
+impl SqliteDatabase {
+    /// A database in the file at path, which the first write creates if it does not exist.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock_timeout: LOCK_TIMEOUT,
+        }
+    }
+
+    /// Wait at most lock_timeout for another connection to finish writing before giving up.
+    pub fn with_lock_timeout(mut self, lock_timeout: Duration) -> Self {
+        self.lock_timeout = lock_timeout;
+        self
+    }
+
+    /// Open the database to write it, creating it and its table of schema versions if needed.
+    fn connect(&self) -> Result<Connection<'_>> {
+        let connection = self.open(OpenFlags::default())?;
+
+        connection.execute(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (collection TEXT PRIMARY KEY, version INTEGER NOT NULL)",
+            quote(SCHEMA_TABLE)
+        ))?;
+
+        Ok(connection)
+    }
+
+    /// Open the database to read it, leaving it as it is, or none if it does not exist.
+    fn connect_read_only(&self) -> Result<Option<Connection<'_>>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+
+        self.open(OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+            .map(Some)
+    }
+
+    fn open(&self, flags: OpenFlags) -> Result<Connection<'_>> {
+        let connection = Connection {
+            connection: rusqlite::Connection::open_with_flags(&self.path, flags)
+                .map_err(|error| self.sql_error(error))?,
+            database: self,
+        };
+
+        connection
+            .connection
+            .busy_timeout(self.lock_timeout)
+            .map_err(|error| self.sql_error(error))?;
+
+        Ok(connection)
+    }
+
+    /// The error of an invalid table, or of one of its rows.
+    fn table_error(&self, table: &str, rowid: Option<i64>, message: String) -> Error {
+        Error::Table {
+            path: self.path.clone(),
+            table: String::from(table),
+            rowid,
+            message,
+        }
+    }
+
+    /// The error of a failed call, which is a busy lock if another connection kept the database
+    /// for longer than the lock timeout.
+    fn sql_error(&self, error: rusqlite::Error) -> Error {
+        match error.sqlite_error_code() {
+            Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) => Error::Locked {
+                path: self.path.clone(),
+                timeout: self.lock_timeout,
+            },
+            _ => Error::Sqlite {
+                path: self.path.clone(),
+                source: error,
+            },
+        }
+    }
+
+    /// Whether the table of a collection exists in the current layout of T.
+    fn current<T: CsvRecord>(&self, connection: &Connection, table: &str) -> Result<bool> {
+        Ok(connection.columns(table)? == headers::<T>(self, table)?
+            && connection.version(table)? == T::VERSION)
+    }
+
+    /// Read the entities of a table matching where_filter on a read-only connection,
+    /// none if the database does not exist.
+    fn read_only<T, P>(
+        &self,
+        from: &str,
+        condition: &Condition,
+        where_filter: P,
+        strict: bool,
+    ) -> Result<(Option<Vec<T>>, Vec<Error>)>
+    where
+        T: CsvRecord,
+        P: FnMut(&T) -> bool,
+    {
+        match self.connect_read_only()? {
+            Some(connection) => self.read(&connection, from, condition, where_filter, strict),
+            None => Ok((None, Vec::new())),
+        }
+    }
+
+    /// Read the entities of a table matching where_filter, in the order they were written.
+    ///
+    /// The rows are looked up with condition first, which where_filter must imply,
+    /// so invalid rows that condition excludes are not reported.
+    fn read<T, P>(
+        &self,
+        connection: &Connection,
+        from: &str,
+        condition: &Condition,
+        mut where_filter: P,
+        strict: bool,
+    ) -> Result<(Option<Vec<T>>, Vec<Error>)>
+    where
+        T: CsvRecord,
+        P: FnMut(&T) -> bool,
+    {
+        let mut entities = Vec::new();
+        let warnings = self.scan(connection, from, condition, strict, |_, entity: T| {
+            if where_filter(&entity) {
+                entities.push(entity);
+            }
+        })?;
+
+        match entities.is_empty() {
+            true => Ok((None, warnings)),
+            false => Ok((Some(entities), warnings)),
+        }
+    }
+
+    /// Parse the rows of a table matching condition in order, passing each one to visit with its rowid,
+    /// and return the errors of the invalid rows, unless strict makes the first one fail the scan.
+    ///
+    /// Tables in an older layout are scanned whole, as condition applies to the current one.
+    fn scan<T: CsvRecord>(
+        &self,
+        connection: &Connection,
+        from: &str,
+        condition: &Condition,
+        strict: bool,
+        mut visit: impl FnMut(i64, T),
+    ) -> Result<Vec<Error>> {
+        let columns = connection.columns(from)?;
+
+        if columns.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let version = connection.version(from)?;
+
+        if version > T::VERSION {
+            return Err(self.table_error(
+                from,
+                None,
+                format!(
+                    "schema version {version} is newer than the supported version {}",
+                    T::VERSION
+                ),
+            ));
+        }
+
+        let headers = headers::<T>(self, from)?;
+        let condition = match version == T::VERSION && columns == headers {
+            true => condition,
+            false => &Condition::default(),
+        };
+        // Columns the table does not have yet are read as empty, like missing CSV columns.
+        let selected: Vec<String> = headers
+            .iter()
+            .map(|name| match columns.iter().any(|column| column == name) {
+                true => quote(name),
+                false => String::from("''"),
+            })
+            .collect();
+        let mut warnings = Vec::new();
+
+        connection.query(
+            &format!(
+                "SELECT rowid, {} FROM {}{} ORDER BY rowid",
+                selected.join(", "),
+                quote(from),
+                condition.sql()
+            ),
+            &condition.values,
+            |row| {
+                let rowid: i64 = row.get(0)?;
+                let fields = (1..=selected.len())
+                    .map(|column| text(row, column))
+                    .collect::<rusqlite::Result<Vec<String>>>()?;
+                let fields = match version < T::VERSION {
+                    true => T::migrate(version, fields),
+                    false => Ok(fields),
+                };
+                let entity = fields
+                    .and_then(|fields| T::from_fields(&fields))
+                    .map_err(|message| self.table_error(from, Some(rowid), message));
+
+                match entity {
+                    Ok(entity) => visit(rowid, entity),
+                    Err(error) if !strict => warnings.push(error),
+                    Err(error) => return Ok(Err(error)),
+                }
+
+                Ok(Ok(()))
+            },
+        )?;
+
+        Ok(warnings)
+    }
+
+    /// The rowids of the rows matching where_filter or equal to one that does,
+    /// the same rows as the default update and delete remove. Invalid rows make it fail.
+    ///
+    /// Rows are equal when their fields are, which are looked up in a set.
+    fn matching<T, P>(
+        &self,
+        connection: &Connection,
+        from: &str,
+        where_filter: P,
+    ) -> Result<Vec<i64>>
+    where
+        T: CsvRecord + PartialEq,
+        P: FnMut(&&T) -> bool,
+    {
+        let mut rows = Vec::new();
+
+        self.scan(
+            connection,
+            from,
+            &Condition::default(),
+            true,
+            |rowid, entity: T| rows.push((rowid, entity)),
+        )?;
+
+        let matched: HashSet<Vec<String>> = rows
+            .iter()
+            .map(|(_, entity)| entity)
+            .filter(where_filter)
+            .map(|entity| entity.to_fields())
+            .collect();
+
+        Ok(rows
+            .iter()
+            .filter(|(_, entity)| matched.contains(&entity.to_fields()))
+            .map(|(rowid, _)| *rowid)
+            .collect())
+    }
+
+    /// Replace the rows of the table of a collection with the entities, in the current layout of T.
+    ///
+    /// The table is only recreated if its columns change.
+    fn replace<T: CsvRecord>(
+        &self,
+        connection: &Connection,
+        to: &str,
+        entities: &[&T],
+    ) -> Result<()> {
+        let headers = headers::<T>(self, to)?;
+        let table = quote(to);
+
+        match connection.columns(to)? == headers {
+            true => connection.execute(&format!("DELETE FROM {table}"))?,
+            false => {
+                connection.execute(&format!("DROP TABLE IF EXISTS {table}"))?;
+                connection.execute(&format!(
+                    "CREATE TABLE {table} ({})",
+                    headers
+                        .iter()
+                        .map(|name| format!("{} TEXT", quote(name)))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))?;
+            }
+        }
+
+        for (name, _, expressions) in INDEXES.iter().filter(|(_, columns, _)| {
+            columns
+                .iter()
+                .all(|column| headers.iter().any(|name| name == column))
+        }) {
+            connection.execute(&format!(
+                "CREATE INDEX IF NOT EXISTS {} ON {table} ({expressions})",
+                quote(&format!("{to}_{name}"))
+            ))?;
+        }
+
+        for entity in entities {
+            connection.insert_row(to, *entity)?;
+        }
+
+        connection
+            .connection
+            .execute(
+                &format!(
+                    "INSERT OR REPLACE INTO {} (collection, version) VALUES (?1, ?2)",
+                    quote(SCHEMA_TABLE)
+                ),
+                params![to, T::VERSION],
+            )
+            .map_err(|error| self.sql_error(error))?;
+
+        Ok(())
+    }
+}
+
+impl Storage for SqliteDatabase {
+    fn select<T, P>(&self, from: &str, where_filter: P) -> Result<Option<Vec<T>>>
+    where
+        T: CsvRecord,
+        P: FnMut(&T) -> bool,
+    {
+        self.read_only(from, &Condition::default(), where_filter, true)
+            .map(|(entities, _warnings)| entities)
+    }
+
+    fn select_lenient<T, P>(
+        &self,
+        from: &str,
+        where_filter: P,
+    ) -> Result<(Option<Vec<T>>, Vec<Error>)>
+    where
+        T: CsvRecord,
+        P: FnMut(&T) -> bool,
+    {
+        self.read_only(from, &Condition::default(), where_filter, false)
+    }
+
+    /// Looks the rows up through the index of the channel and datetime columns.
+    fn select_range(
+        &self,
+        from: &str,
+        channel: Option<&str>,
+        range: &DateRange,
+    ) -> Result<Option<Vec<FirstResult>>> {
+        self.read_only(
+            from,
+            &Condition::range(channel, range),
+            in_range(channel, range),
+            true,
+        )
+        .map(|(results, _warnings)| results)
+    }
+
+    /// Looks the rows up through the index of the channel and datetime columns,
+    /// so only the invalid rows of the channel and range are reported.
+    fn select_range_lenient(
+        &self,
+        from: &str,
+        channel: Option<&str>,
+        range: &DateRange,
+    ) -> Result<(Option<Vec<FirstResult>>, Vec<Error>)> {
+        self.read_only(
+            from,
+            &Condition::range(channel, range),
+            in_range(channel, range),
+            false,
+        )
+    }
+
+    /// Inserts a row, unless the table is in an older layout, which is migrated by rewriting it.
+    fn insert<T>(&self, into: &str, entity: T) -> Result<()>
+    where
+        T: CsvRecord,
+    {
+        let connection = self.connect()?;
+
+        connection.transaction(|| {
+            if !self.current::<T>(&connection, into)? {
+                let (entities, _warnings) =
+                    self.read(&connection, into, &Condition::default(), |_| true, true)?;
+                let mut entities = entities.unwrap_or_default();
+
+                entities.push(entity);
+
+                return self.replace(&connection, into, &entities.iter().collect::<Vec<_>>());
+            }
+
+            connection.insert_row(into, &entity)
+        })
+    }
+
+    /// Runs in a transaction that takes the write lock of the database first.
+    fn rewrite<T, F>(&self, collection: &str, change: F) -> Result<()>
+    where
+        T: CsvRecord,
+        F: FnOnce(Vec<T>) -> Result<Vec<T>>,
+    {
+        let connection = self.connect()?;
+
+        connection.transaction(|| {
+            let (entities, _warnings) = self.read(
+                &connection,
+                collection,
+                &Condition::default(),
+                |_| true,
+                true,
+            )?;
+            let entities = change(entities.unwrap_or_default())?;
+
+            self.replace(
+                &connection,
+                collection,
+                &entities.iter().collect::<Vec<_>>(),
+            )
+        })
+    }
+
+    fn write<T: CsvRecord>(&self, to: &str, entities: &[&T]) -> Result<()> {
+        let connection = self.connect()?;
+
+        connection.transaction(|| self.replace(&connection, to, entities))
+    }
+
+    /// Updates the first matching row in place and deletes the others, or inserts the entity
+    /// if none matches, unless the table is in an older layout, which is migrated by rewriting it.
+    fn update<T, P>(&self, from: &str, entity: T, where_filter: P) -> Result<()>
+    where
+        T: CsvRecord + PartialEq,
+        P: FnMut(&&T) -> bool,
+    {
+        let connection = self.connect()?;
+
+        connection.transaction(|| {
+            if !self.current::<T>(&connection, from)? {
+                let (entities, _warnings) =
+                    self.read(&connection, from, &Condition::default(), |_| true, true)?;
+                let mut keep = remove(entities.unwrap_or_default(), where_filter);
+
+                keep.push(entity);
+
+                return self.replace(&connection, from, &keep.iter().collect::<Vec<_>>());
+            }
+
+            let rowids = self.matching(&connection, from, where_filter)?;
+            let Some((first, others)) = rowids.split_first() else {
+                return connection.insert_row(from, &entity);
+            };
+            let headers = headers::<T>(self, from)?;
+            let mut values = row(&entity);
+
+            values.push(first.to_string());
+            connection
+                .connection
+                .execute(
+                    &format!(
+                        "UPDATE {} SET {} WHERE rowid = ?{}",
+                        quote(from),
+                        headers
+                            .iter()
+                            .enumerate()
+                            .map(|(index, name)| format!("{} = ?{}", quote(name), index + 1))
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        headers.len() + 1
+                    ),
+                    params_from_iter(&values),
+                )
+                .map_err(|error| self.sql_error(error))?;
+
+            connection.delete_rows(from, others)
+        })
+    }
+
+    /// Deletes the matching rows, unless the table is in an older layout,
+    /// which is migrated by rewriting it.
+    fn delete<T, P>(&self, from: &str, where_filter: P) -> Result<()>
+    where
+        T: CsvRecord + PartialEq,
+        P: FnMut(&&T) -> bool,
+    {
+        let connection = self.connect()?;
+
+        connection.transaction(|| {
+            if !self.current::<T>(&connection, from)? {
+                let (entities, _warnings) =
+                    self.read(&connection, from, &Condition::default(), |_| true, true)?;
+
+                return match entities {
+                    Some(entities) => self.replace(
+                        &connection,
+                        from,
+                        &remove(entities, where_filter).iter().collect::<Vec<_>>(),
+                    ),
+                    None => Ok(()),
+                };
+            }
+
+            let rowids = self.matching(&connection, from, where_filter)?;
+
+            connection.delete_rows(from, &rowids)
+        })
+    }
+}
+
+/// A SQL condition on the rows of a table, with the values of its parameters in order.
+#[derive(Debug, Default)]
+struct Condition {
+    clauses: Vec<String>,
+    values: Vec<String>,
+}
+
+impl Condition {
+    /// The rows of a channel (in any casing) within range.
+    ///
+    /// SQLite only lowercases ASCII letters, so other channels are matched by the caller alone.
+    fn range(channel: Option<&str>, range: &DateRange) -> Self {
+        let mut condition = Self::default();
+        let datetime = quote(DATETIME_COLUMN);
+
+        if let Some(channel) = channel.filter(|channel| channel.is_ascii()) {
+            condition.push("lower(\"channel\") =", channel.to_ascii_lowercase());
+        }
+
+        match range.start {
+            Bound::Included(start) => condition.push(&format!("{datetime} >="), stored(start)),
+            Bound::Excluded(start) => condition.push(&format!("{datetime} >"), stored(start)),
+            Bound::Unbounded => {}
+        }
+
+        match range.end {
+            Bound::Included(end) => condition.push(&format!("{datetime} <="), stored(end)),
+            Bound::Excluded(end) => condition.push(&format!("{datetime} <"), stored(end)),
+            Bound::Unbounded => {}
+        }
+
+        condition
+    }
+
+    /// Add a clause comparing to a value, as in channel = value.
+    fn push(&mut self, comparison: &str, value: String) {
+        self.values.push(value);
+        self.clauses
+            .push(format!("{comparison} ?{}", self.values.len()));
+    }
+
+    /// The WHERE clause of the condition, if any, with a leading space.
+    fn sql(&self) -> String {
+        match self.clauses.is_empty() {
+            true => String::new(),
+            false => format!(" WHERE {}", self.clauses.join(" AND ")),
+        }
+    }
+}
+
+/// An open connection, closed when dropped.
+struct Connection<'a> {
+    connection: rusqlite::Connection,
+    database: &'a SqliteDatabase,
+}
+
+impl Connection<'_> {
+    fn execute(&self, sql: &str) -> Result<()> {
+        self.connection
+            .execute_batch(sql)
+            .map_err(|error| self.database.sql_error(error))
+    }
+
+    /// Run a query with values bound to its first parameters, passing each row to visit in turn
+    /// until it returns an error of its own.
+    fn query(
+        &self,
+        sql: &str,
+        values: &[String],
+        mut visit: impl FnMut(&SqlRow) -> rusqlite::Result<Result<()>>,
+    ) -> Result<()> {
+        let error = |error| self.database.sql_error(error);
+        let mut statement = self.connection.prepare(sql).map_err(error)?;
+        let mut rows = statement.query(params_from_iter(values)).map_err(error)?;
+
+        while let Some(row) = rows.next().map_err(error)? {
+            visit(row).map_err(error)??;
+        }
+
+        Ok(())
+    }
+
+    /// Run f in a transaction, committing if it succeeds and rolling back otherwise.
+    fn transaction<R>(&self, f: impl FnOnce() -> Result<R>) -> Result<R> {
+        self.execute("BEGIN IMMEDIATE")?;
+
+        match f() {
+            Ok(result) => {
+                self.execute("COMMIT")?;
+                Ok(result)
+            }
+            Err(error) => {
+                let _ = self.execute("ROLLBACK");
+                Err(error)
+            }
+        }
+    }
+
+    /// Columns of the table of a collection, in order, or none if it does not exist.
+    fn columns(&self, table: &str) -> Result<Vec<String>> {
+        let mut columns = Vec::new();
+
+        self.query(
+            &format!("PRAGMA table_info({})", quote(table)),
+            &[],
+            |row| {
+                columns.push(row.get(1)?);
+                Ok(Ok(()))
+            },
+        )?;
+
+        Ok(columns)
+    }
+
+    /// Schema version of a collection, 1 if it was not recorded (as in databases that first_top did not create).
+    fn version(&self, collection: &str) -> Result<u32> {
+        let mut version = 1;
+
+        if self.columns(SCHEMA_TABLE)?.is_empty() {
+            return Ok(version);
+        }
+
+        self.query(
+            &format!(
+                "SELECT version FROM {} WHERE collection = ?1",
+                quote(SCHEMA_TABLE)
+            ),
+            &[String::from(collection)],
+            |row| {
+                version = u32::try_from(row.get::<_, i64>(0)?).unwrap_or(u32::MAX);
+                Ok(Ok(()))
+            },
+        )?;
+
+        Ok(version)
+    }
+
+    fn insert_row<T: CsvRecord>(&self, table: &str, entity: &T) -> Result<()> {
+        let headers = headers::<T>(self.database, table)?;
+        let mut statement = self
+            .connection
+            .prepare_cached(&format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                quote(table),
+                headers
+                    .iter()
+                    .map(|name| quote(name))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                (1..=headers.len())
+                    .map(|index| format!("?{index}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+            .map_err(|error| self.database.sql_error(error))?;
+
+        statement
+            .execute(params_from_iter(row(entity)))
+            .map_err(|error| self.database.sql_error(error))?;
+
+        Ok(())
+    }
+
+    fn delete_rows(&self, table: &str, rowids: &[i64]) -> Result<()> {
+        let mut statement = self
+            .connection
+            .prepare(&format!("DELETE FROM {} WHERE rowid = ?1", quote(table)))
+            .map_err(|error| self.database.sql_error(error))?;
+
+        for rowid in rowids {
+            statement
+                .execute([rowid])
+                .map_err(|error| self.database.sql_error(error))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The text of a column of a row, with NULL read as empty.
+fn text(row: &SqlRow, column: usize) -> rusqlite::Result<String> {
+    Ok(row.get::<_, Option<String>>(column)?.unwrap_or_default())
+}
+
+/// The headers of T, which name the columns of its tables.
+fn headers<T: CsvRecord>(database: &SqliteDatabase, table: &str) -> Result<Vec<String>> {
+    match T::HEADERS.is_empty() {
+        true => Err(database.table_error(
+            table,
+            None,
+            String::from("records without headers cannot be stored in SQLite"),
+        )),
+        false => Ok(T::HEADERS.iter().map(|name| String::from(*name)).collect()),
+    }
+}
+
+/// Quote an identifier for SQL.
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// The values of the columns of an entity, with its datetime in the stored format
+/// (or as it is if it does not parse).
+fn row<T: CsvRecord>(entity: &T) -> Vec<String> {
+    T::HEADERS
+        .iter()
+        .zip(entity.to_fields())
+        .map(|(name, value)| match *name == DATETIME_COLUMN {
+            true => value.parse().map(stored).unwrap_or(value),
+            false => value,
+        })
+        .collect()
+}
+
+/// A datetime in the stored format.
+fn stored(datetime: DateTime<Utc>) -> String {
+    datetime.format(DATETIME_FORMAT).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Datelike, NaiveDate, NaiveTime, TimeDelta, TimeZone};
+
+    use super::*;
+    use crate::openings::Opening;
+    use crate::storage::SQLITE_FILE;
+    use crate::test_support::TempDir;
+
+    fn database(dir: &TempDir) -> SqliteDatabase {
+        SqliteDatabase::new(dir.join(SQLITE_FILE))
+    }
+
+    fn result(nick: &str, channel: &str, day: u32, hour: u32) -> FirstResult {
+        FirstResult {
+            nick: String::from(nick),
+            channel: String::from(channel),
+            datetime: Utc.with_ymd_and_hms(2025, 3, day, hour, 0, 0).unwrap(),
+            timezone: String::from("Europe/Paris"),
+        }
+    }
+
+    fn opening(channel: &str, day: u32, hour: u32) -> Opening {
+        Opening {
+            channel: String::from(channel),
+            day: NaiveDate::from_ymd_opt(2025, 3, day).unwrap(),
+            time: NaiveTime::from_hms_opt(hour, 0, 0).unwrap(),
+            rule: String::from("fixed"),
+        }
+    }
+
+    fn results() -> Vec<FirstResult> {
+        vec![
+            result("alice", "#Test", 2, 9),
+            result("bob", "#other", 1, 9),
+            result("carol", "#test", 1, 10),
+            result("dave", "#test", 3, 9),
+        ]
+    }
+
+    fn insert_all<T: CsvRecord>(database: &SqliteDatabase, entities: Vec<T>) {
+        for entity in entities {
+            database.insert("collection", entity).unwrap();
+        }
+    }
+
+    /// The details of the query plan of a select of the rows matching condition.
+    fn query_plan(database: &SqliteDatabase, condition: &Condition) -> Vec<String> {
+        let mut details = Vec::new();
+
+        database
+            .connect()
+            .unwrap()
+            .query(
+                &format!(
+                    "EXPLAIN QUERY PLAN SELECT rowid FROM \"collection\"{} ORDER BY rowid",
+                    condition.sql()
+                ),
+                &condition.values,
+                |row| {
+                    details.push(row.get(3)?);
+                    Ok(Ok(()))
+                },
+            )
+            .unwrap();
+
+        details
+    }
+
+    #[test]
+    fn select_returns_rows_in_insertion_order() {
+        let dir = TempDir::new("sqlite-order");
+        let database = database(&dir);
+
+        insert_all(&database, results());
+
+        assert_eq!(
+            database
+                .select("collection", |_: &FirstResult| true)
+                .unwrap(),
+            Some(results())
+        );
+        assert_eq!(
+            database.select("missing", |_: &FirstResult| true).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn datetimes_are_stored_as_sortable_text() {
+        let dir = TempDir::new("sqlite-datetimes");
+        let database = database(&dir);
+        let mut late = result("alice", "#test", 1, 9);
+
+        late.datetime += TimeDelta::nanoseconds(1_500);
+        insert_all(&database, vec![late, result("bob", "#test", 1, 9)]);
+
+        let mut datetimes: Vec<String> = Vec::new();
+
+        database
+            .connect()
+            .unwrap()
+            .query(
+                "SELECT datetime FROM \"collection\" ORDER BY datetime",
+                &[],
+                |row| {
+                    datetimes.push(row.get(0)?);
+                    Ok(Ok(()))
+                },
+            )
+            .unwrap();
+
+        assert_eq!(
+            datetimes,
+            [
+                "2025-03-01T09:00:00.000000000Z",
+                "2025-03-01T09:00:00.000001500Z"
+            ]
+        );
+    }
+
+    #[test]
+    fn select_range_matches_channels_in_any_casing_within_bounds() {
+        let dir = TempDir::new("sqlite-range");
+        let database = database(&dir);
+        let start = Utc.with_ymd_and_hms(2025, 3, 1, 10, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2025, 3, 3, 9, 0, 0).unwrap();
+        let nicks = |range: DateRange| -> Vec<String> {
+            database
+                .select_range("collection", Some("#TEST"), &range)
+                .unwrap()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|r| r.nick)
+                .collect()
+        };
+
+        insert_all(&database, results());
+
+        assert_eq!(nicks(DateRange::default()), ["alice", "carol", "dave"]);
+        assert_eq!(
+            nicks(DateRange {
+                start: Bound::Included(start),
+                end: Bound::Included(end),
+            }),
+            ["alice", "carol", "dave"]
+        );
+        assert_eq!(
+            nicks(DateRange {
+                start: Bound::Excluded(start),
+                end: Bound::Excluded(end),
+            }),
+            ["alice"]
+        );
+    }
+
+    #[test]
+    fn select_range_looks_results_up_through_the_indexes() {
+        let dir = TempDir::new("sqlite-plan");
+        let database = database(&dir);
+        let since = DateRange {
+            start: Bound::Included(Utc.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap()),
+            end: Bound::Unbounded,
+        };
+        let day = DateRange {
+            end: Bound::Excluded(Utc.with_ymd_and_hms(2025, 3, 2, 0, 0, 0).unwrap()),
+            ..since
+        };
+
+        insert_all(&database, results());
+
+        assert!(
+            query_plan(&database, &Condition::range(Some("#Test"), &since))
+                .iter()
+                .any(|detail| detail.contains("INDEX collection_channel_datetime"))
+        );
+        assert!(
+            query_plan(&database, &Condition::range(None, &day))
+                .iter()
+                .any(|detail| detail.contains("INDEX collection_datetime"))
+        );
+    }
+
+    #[test]
+    fn update_replaces_the_matching_rows_in_place() {
+        let dir = TempDir::new("sqlite-update");
+        let database = database(&dir);
+        let day = |o: &&Opening| o.channel.to_lowercase() == "#test" && o.day.day() == 1;
+
+        insert_all(
+            &database,
+            vec![
+                opening("#test", 1, 9),
+                opening("#test", 2, 9),
+                opening("#Test", 1, 10),
+            ],
+        );
+        database
+            .update("collection", opening("#test", 1, 11), day)
+            .unwrap();
+
+        assert_eq!(
+            database.select("collection", |_: &Opening| true).unwrap(),
+            Some(vec![opening("#test", 1, 11), opening("#test", 2, 9)])
+        );
+
+        database
+            .update("collection", opening("#test", 3, 9), |o: &&Opening| {
+                o.day.day() == 3
+            })
+            .unwrap();
+
+        assert_eq!(
+            database.select("collection", |_: &Opening| true).unwrap(),
+            Some(vec![
+                opening("#test", 1, 11),
+                opening("#test", 2, 9),
+                opening("#test", 3, 9)
+            ])
+        );
+    }
+
+    #[test]
+    fn delete_removes_the_matching_rows_and_their_duplicates() {
+        let dir = TempDir::new("sqlite-delete");
+        let database = database(&dir);
+
+        insert_all(
+            &database,
+            vec![
+                opening("#test", 1, 9),
+                opening("#test", 2, 9),
+                opening("#test", 1, 9),
+            ],
+        );
+        database
+            .delete("collection", |o: &&Opening| o.day.day() == 1)
+            .unwrap();
+        database.delete("missing", |_: &&Opening| true).unwrap();
+
+        assert_eq!(
+            database.select("collection", |_: &Opening| true).unwrap(),
+            Some(vec![opening("#test", 2, 9)])
+        );
+        assert!(
+            database
+                .connect()
+                .unwrap()
+                .columns("missing")
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn newer_schema_versions_are_rejected() {
+        let dir = TempDir::new("sqlite-version");
+        let database = database(&dir);
+
+        insert_all(&database, vec![opening("#test", 1, 9)]);
+        database
+            .connect()
+            .unwrap()
+            .execute("UPDATE \"first_top_schema\" SET version = 99")
+            .unwrap();
+
+        assert!(matches!(
+            database.select("collection", |_: &Opening| true),
+            Err(Error::Table { table, rowid: None, message, .. })
+                if table == "collection" && message.contains("schema version 99")
+        ));
+    }
+
+    #[test]
+    fn nul_bytes_are_rejected() {
+        let dir = TempDir::new("sqlite-nul");
+        let database = database(&dir);
+
+        assert!(matches!(
+            database.insert("collec\0tion", opening("#test", 1, 9)),
+            Err(Error::Sqlite { .. })
+        ));
+
+        insert_all(&database, vec![opening("#test", 1, 9)]);
+
+        assert!(matches!(
+            database.select("collec\0tion", |_: &Opening| true),
+            Err(Error::Sqlite { .. })
+        ));
+    }
+
+    #[test]
+    fn writes_fail_as_locked_while_another_connection_writes() {
+        let dir = TempDir::new("sqlite-locked");
+        let database = database(&dir);
+        let other = SqliteDatabase::new(&database.path).with_lock_timeout(Duration::ZERO);
+        let connection = database.connect().unwrap();
+
+        connection.execute("BEGIN IMMEDIATE").unwrap();
+
+        assert!(matches!(
+            other.insert("collection", opening("#test", 1, 9)),
+            Err(Error::Locked { .. })
+        ));
+
+        connection.execute("ROLLBACK").unwrap();
+        other.insert("collection", opening("#test", 1, 9)).unwrap();
+    }
+
+    #[test]
+    fn reads_leave_the_database_as_it_is() {
+        let dir = TempDir::new("sqlite-read-only");
+        let database = database(&dir);
+
+        assert_eq!(
+            database.select("collection", |_: &Opening| true).unwrap(),
+            None
+        );
+        assert!(!database.path.exists());
+
+        // A database that first_top did not create, without the table of schema versions.
+        rusqlite::Connection::open(&database.path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE collection (channel TEXT, day TEXT, time TEXT, rule TEXT);
+                 INSERT INTO collection VALUES ('#test', '2025-03-01', '09:00:00', 'fixed');",
+            )
+            .unwrap();
+
+        assert_eq!(
+            database.select("collection", |_: &Opening| true).unwrap(),
+            Some(vec![opening("#test", 1, 9)])
+        );
+        assert!(
+            database
+                .open(OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .unwrap()
+                .columns(SCHEMA_TABLE)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn invalid_rows_are_reported_by_their_row_id() {
+        let dir = TempDir::new("sqlite-row-id");
+        let database = database(&dir);
+
+        insert_all(
+            &database,
+            vec![opening("#test", 1, 9), opening("#test", 2, 9)],
+        );
+        database
+            .connect()
+            .unwrap()
+            .execute("UPDATE \"collection\" SET time = 'noon' WHERE rowid = 2")
+            .unwrap();
+
+        let (openings, warnings) = database
+            .select_lenient("collection", |_: &Opening| true)
+            .unwrap();
+
+        assert_eq!(openings, Some(vec![opening("#test", 1, 9)]));
+        assert!(matches!(
+            &warnings[..],
+            [error @ Error::Table { rowid: Some(2), .. }]
+                if error.to_string().contains(": row id 2 of collection: ")
+        ));
+        assert!(matches!(
+            database.select("collection", |_: &Opening| true),
+            Err(Error::Table { rowid: Some(2), .. })
+        ));
+    }
+}
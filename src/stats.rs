@@ -0,0 +1,307 @@
+//! Statistics of a player over the attempts of a channel.
+
+use chrono::{Datelike, NaiveDate};
+use itertools::Itertools;
+
+use crate::ranking::Attempt;
+use crate::rules::Rules;
+
+/// Statistics of one month of a player.
+#[derive(Debug, PartialEq)]
+pub struct MonthStats {
+    /// Year of the month.
+    pub year: i32,
+    /// Month of the year, from 1.
+    pub month: u32,
+    /// Number of attempts of the player in the month.
+    pub attempts: usize,
+    /// Number of days the player won in the month.
+    pub wins: usize,
+    /// Best valid delta in microseconds, if any attempt was valid.
+    pub best_us: Option<i64>,
+}
+
+/// Personal statistics of a player over the attempts of a channel.
+#[derive(Debug, PartialEq)]
+pub struct PlayerStats {
+    /// Nick of the player, as written in their last attempt.
+    pub nick: String,
+    /// Number of attempts of the player.
+    pub attempts: usize,
+    /// Number of days the player won.
+    pub wins: usize,
+    /// Number of attempts with a valid delta (positive and not above the cutoff).
+    pub valid: usize,
+    /// Best valid delta in microseconds, if any attempt was valid.
+    pub best_us: Option<i64>,
+    /// Median of the valid deltas in microseconds, if any attempt was valid.
+    pub median_us: Option<i64>,
+    /// Mean of the valid deltas in microseconds, if any attempt was valid.
+    pub mean_us: Option<i64>,
+    /// Longest run of consecutive days won.
+    pub longest_streak: usize,
+    /// Run of consecutive days won up to the last day the channel played.
+    pub current_streak: usize,
+    /// Attempts that landed at or before the opening (jump starts).
+    pub early: usize,
+    /// Attempts that landed after the cutoff.
+    pub late: usize,
+    /// Statistics of each month the player played, in chronological order.
+    pub months: Vec<MonthStats>,
+}
+
+/// Compute the statistics of a nick (case insensitive) from the deltas of every day.
+///
+/// The deltas of each day must be sorted by lowest, with the days in chronological order,
+/// as the winner of a day is its lowest delta when that delta is valid.
+/// Returns None if the nick has no attempts.
+pub fn player_stats(
+    nick: &str,
+    days: &[(NaiveDate, Vec<Attempt>)],
+    rules: &Rules,
+) -> Option<PlayerStats> {
+    let key = nick.to_lowercase();
+    let valid = |micros: i64| micros > 0 && micros <= rules.cutoff_us;
+
+    let mut stats = PlayerStats {
+        nick: String::from(nick),
+        attempts: 0,
+        wins: 0,
+        valid: 0,
+        best_us: None,
+        median_us: None,
+        mean_us: None,
+        longest_streak: 0,
+        current_streak: 0,
+        early: 0,
+        late: 0,
+        months: Vec::new(),
+    };
+    let mut deltas: Vec<i64> = Vec::new();
+    let mut streak = 0;
+    let mut last_win: Option<NaiveDate> = None;
+
+    for (day, attempts) in days {
+        let won = attempts
+            .first()
+            .is_some_and(|winner| valid(winner.delta_us) && winner.nick.to_lowercase() == key);
+
+        if won {
+            streak = match last_win {
+                Some(last) if last.succ_opt() == Some(*day) => streak + 1,
+                Some(last) if last == *day => streak,
+                _ => 1,
+            };
+            last_win = Some(*day);
+            stats.longest_streak = stats.longest_streak.max(streak);
+        }
+
+        for attempt in attempts {
+            if attempt.nick.to_lowercase() != key {
+                continue;
+            }
+
+            let micros = attempt.delta_us;
+
+            // Keep the nick as the player wrote it.
+            stats.nick = attempt.nick.clone();
+            stats.attempts += 1;
+
+            let month = match stats.months.last_mut() {
+                Some(month) if month.year == day.year() && month.month == day.month() => month,
+                _ => {
+                    stats.months.push(MonthStats {
+                        year: day.year(),
+                        month: day.month(),
+                        attempts: 0,
+                        wins: 0,
+                        best_us: None,
+                    });
+                    stats.months.last_mut()?
+                }
+            };
+
+            month.attempts += 1;
+
+            if micros <= 0 {
+                stats.early += 1;
+            } else if micros > rules.cutoff_us {
+                stats.late += 1;
+            } else {
+                deltas.push(micros);
+                month.best_us = Some(month.best_us.map_or(micros, |best| best.min(micros)));
+            }
+        }
+
+        if won {
+            stats.wins += 1;
+
+            if let Some(month) = stats.months.last_mut() {
+                month.wins += 1;
+            }
+        }
+    }
+
+    if stats.attempts == 0 {
+        return None;
+    }
+
+    // The current streak only counts if it reaches the last day the channel played.
+    if let (Some(last), Some((last_day, _))) = (last_win, days.last())
+        && last == *last_day
+    {
+        stats.current_streak = streak;
+    }
+
+    let deltas: Vec<i64> = deltas.into_iter().sorted().collect();
+
+    stats.valid = deltas.len();
+    stats.best_us = deltas.first().copied();
+    stats.median_us = match deltas.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((deltas[len / 2 - 1] + deltas[len / 2]) / 2),
+        len => Some(deltas[len / 2]),
+    };
+    stats.mean_us = match deltas.is_empty() {
+        true => None,
+        false => Some(deltas.iter().sum::<i64>() / deltas.len() as i64),
+    };
+
+    Some(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use chrono_tz::Tz;
+
+    use super::*;
+
+    fn day(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, month, day).unwrap()
+    }
+
+    fn attempt(nick: &str, delta_ms: i64) -> Attempt {
+        Attempt {
+            nick: String::from(nick),
+            delta_us: delta_ms * 1000,
+            opening: Tz::UTC.with_ymd_and_hms(2025, 3, 1, 8, 0, 0).unwrap(),
+        }
+    }
+
+    /// A day with its attempts sorted by lowest delta, as daily_deltas returns them.
+    fn on(day: NaiveDate, attempts: Vec<Attempt>) -> (NaiveDate, Vec<Attempt>) {
+        (
+            day,
+            attempts.into_iter().sorted_by_key(|a| a.delta_us).collect(),
+        )
+    }
+
+    #[test]
+    fn streaks_count_consecutive_days_won() {
+        let days = [
+            on(day(3, 1), vec![attempt("alice", 100)]),
+            on(day(3, 2), vec![attempt("alice", 100)]),
+            on(day(3, 3), vec![attempt("alice", 100)]),
+            // Lost to bob.
+            on(day(3, 4), vec![attempt("bob", 50), attempt("alice", 100)]),
+            on(day(3, 5), vec![attempt("ALICE", 100)]),
+            // Not played on the 6th.
+            on(day(3, 7), vec![attempt("alice", 100)]),
+            on(day(3, 8), vec![attempt("alice", 100)]),
+        ];
+        let stats = player_stats("Alice", &days, &Rules::default()).unwrap();
+
+        assert_eq!(stats.wins, 6);
+        assert_eq!(stats.longest_streak, 3);
+        assert_eq!(stats.current_streak, 2);
+    }
+
+    #[test]
+    fn current_streaks_end_with_the_last_day_played() {
+        let days = [
+            on(day(3, 1), vec![attempt("alice", 100)]),
+            on(day(3, 2), vec![attempt("alice", 100)]),
+            // A jump start wins nothing, and ends the streak.
+            on(day(3, 3), vec![attempt("alice", -5), attempt("bob", 300)]),
+        ];
+        let stats = player_stats("alice", &days, &Rules::default()).unwrap();
+
+        assert_eq!(stats.longest_streak, 2);
+        assert_eq!(stats.current_streak, 0);
+    }
+
+    #[test]
+    fn averages_count_valid_attempts_only() {
+        let days = [
+            on(
+                day(3, 1),
+                vec![attempt("alice", 400), attempt("alice", -20)],
+            ),
+            on(
+                day(3, 2),
+                vec![attempt("alice", 100), attempt("alice", 1500)],
+            ),
+            on(day(3, 3), vec![attempt("alice", 300)]),
+            on(day(3, 4), vec![attempt("alice", 200)]),
+            on(day(3, 5), vec![attempt("bob", 10)]),
+        ];
+        let stats = player_stats("alice", &days, &Rules::default()).unwrap();
+
+        assert_eq!(stats.attempts, 6);
+        assert_eq!(stats.valid, 4);
+        assert_eq!(stats.early, 1);
+        assert_eq!(stats.late, 1);
+        assert_eq!(stats.best_us, Some(100_000));
+        assert_eq!(stats.median_us, Some(250_000));
+        assert_eq!(stats.mean_us, Some(250_000));
+
+        let odd = player_stats("alice", &days[1..], &Rules::default()).unwrap();
+
+        assert_eq!(odd.median_us, Some(200_000));
+    }
+
+    #[test]
+    fn players_without_valid_attempts_have_no_averages() {
+        let days = [on(
+            day(3, 1),
+            vec![attempt("alice", -5), attempt("alice", 2000)],
+        )];
+        let stats = player_stats("alice", &days, &Rules::default()).unwrap();
+
+        assert_eq!(
+            (stats.valid, stats.best_us, stats.median_us, stats.mean_us),
+            (0, None, None, None)
+        );
+        assert_eq!(player_stats("bob", &days, &Rules::default()), None);
+    }
+
+    #[test]
+    fn months_are_summarised_in_order() {
+        let days = [
+            on(day(2, 27), vec![attempt("alice", 300), attempt("bob", 200)]),
+            on(day(2, 28), vec![attempt("alice", 100)]),
+            on(day(3, 1), vec![attempt("alice", -10)]),
+            on(day(4, 1), vec![attempt("bob", 100)]),
+            on(day(4, 2), vec![attempt("alice", 500)]),
+        ];
+        let month = |month: u32, attempts: usize, wins: usize, best_ms: Option<i64>| MonthStats {
+            year: 2025,
+            month,
+            attempts,
+            wins,
+            best_us: best_ms.map(|best| best * 1000),
+        };
+
+        assert_eq!(
+            player_stats("alice", &days, &Rules::default())
+                .unwrap()
+                .months,
+            [
+                month(2, 2, 1, Some(100)),
+                month(3, 1, 0, None),
+                month(4, 1, 1, Some(500)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Backends the collections can be stored in, behind the [`Storage`] trait.
+
+use crate::FirstResult;
+use crate::database::CsvRecord;
+use crate::dates::DateRange;
+use crate::error::{Error, Result};
+
+/// File of the SQLite database in the data directory.
+pub const SQLITE_FILE: &str = "first_top.sqlite";
+
+/// Where the collections are stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// One CSV file per collection, the format the bot writes.
+    Csv,
+    /// One table per collection in a SQLite database (requires the sqlite feature).
+    Sqlite,
+}
+
+impl Backend {
+    /// Parse the name of a backend as given on the command line or in the config file.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(Backend::Csv),
+            "sqlite" => Some(Backend::Sqlite),
+            _ => None,
+        }
+    }
+
+    /// Name of the backend, as parsed by [`Backend::from_name`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Csv => "csv",
+            Backend::Sqlite => "sqlite",
+        }
+    }
+
+    /// Fail unless this build of first_top supports the backend.
+    pub fn available(&self) -> Result<()> {
+        match self {
+            Backend::Sqlite if !cfg!(feature = "sqlite") => Err(Error::Config(String::from(
+                "The sqlite backend requires first_top to be built with the sqlite feature",
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Collections of records, whatever they are stored in.
+pub trait Storage {
+    /// Select the entities matching where_filter, failing on the first invalid row.
+    fn select<T, P>(&self, from: &str, where_filter: P) -> Result<Option<Vec<T>>>
+    where
+        T: CsvRecord,
+        P: FnMut(&T) -> bool;
+
+    /// Select the entities matching where_filter, skipping invalid rows.
+    ///
+    /// The errors of the skipped rows are returned as warnings, with their positions.
+    fn select_lenient<T, P>(
+        &self,
+        from: &str,
+        where_filter: P,
+    ) -> Result<(Option<Vec<T>>, Vec<Error>)>
+    where
+        T: CsvRecord,
+        P: FnMut(&T) -> bool;
+
+    /// Add an entity to a collection, creating it if needed.
+    fn insert<T>(&self, into: &str, entity: T) -> Result<()>
+    where
+        T: CsvRecord;
+
+    /// Read all the entities of a collection, change them and write them back,
+    /// with no other writer coming in between.
+    ///
+    /// Invalid rows make the whole rewrite fail, rather than being lost.
+    fn rewrite<T, F>(&self, collection: &str, change: F) -> Result<()>
+    where
+        T: CsvRecord,
+        F: FnOnce(Vec<T>) -> Result<Vec<T>>;
+
+    /// Replace the entities of a collection, all at once or not at all.
+    fn write<T: CsvRecord>(&self, to: &str, entities: &[&T]) -> Result<()>;
+
+    /// Select the results of a channel (in any casing), or of all channels, played within range,
+    /// failing on the first invalid row.
+    ///
+    /// Backends that can look the channel and range up without reading every row override it.
+    fn select_range(
+        &self,
+        from: &str,
+        channel: Option<&str>,
+        range: &DateRange,
+    ) -> Result<Option<Vec<FirstResult>>> {
+        self.select(from, in_range(channel, range))
+    }
+
+    /// Select the valid results of a channel (in any casing), or of all channels, played within range,
+    /// with the errors of the skipped rows.
+    fn select_range_lenient(
+        &self,
+        from: &str,
+        channel: Option<&str>,
+        range: &DateRange,
+    ) -> Result<(Option<Vec<FirstResult>>, Vec<Error>)> {
+        self.select_lenient(from, in_range(channel, range))
+    }
+
+    /// Replace the entities matching where_filter with entity, which is added if none match.
+    fn update<T, P>(&self, from: &str, entity: T, where_filter: P) -> Result<()>
+    where
+        T: CsvRecord + PartialEq,
+        P: FnMut(&&T) -> bool,
+    {
+        self.rewrite(from, |entities: Vec<T>| {
+            let mut keep = remove(entities, where_filter);
+
+            keep.push(entity);
+
+            Ok(keep)
+        })
+    }
+
+    /// Remove the entities matching where_filter.
+    fn delete<T, P>(&self, from: &str, where_filter: P) -> Result<()>
+    where
+        T: CsvRecord + PartialEq,
+        P: FnMut(&&T) -> bool,
+    {
+        self.rewrite(from, |entities: Vec<T>| Ok(remove(entities, where_filter)))
+    }
+}
+
+/// Whether a result is of the channel (in any casing), if any, and played within range.
+pub(crate) fn in_range(
+    channel: Option<&str>,
+    range: &DateRange,
+) -> impl FnMut(&FirstResult) -> bool {
+    let channel = channel.map(str::to_lowercase);
+    let range = *range;
+
+    move |r| {
+        channel
+            .as_ref()
+            .is_none_or(|channel| r.channel.to_lowercase() == *channel)
+            && range.contains(&r.datetime)
+    }
+}
+
+/// The entities that neither match where_filter nor equal one that does.
+pub(crate) fn remove<T, P>(entities: Vec<T>, where_filter: P) -> Vec<T>
+where
+    T: PartialEq,
+    P: FnMut(&&T) -> bool,
+{
+    let delete: Vec<&T> = entities.iter().filter(where_filter).collect();
+    let keep: Vec<bool> = entities
+        .iter()
+        .map(|entity| !delete.contains(&entity))
+        .collect();
+
+    entities
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(entity, keep)| keep.then_some(entity))
+        .collect()
+}
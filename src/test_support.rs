@@ -0,0 +1,111 @@
+//! Fixtures shared by the tests of the modules: a temporary directory and the configs using it.
+
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, fs, process};
+
+use crate::config::Config;
+use crate::rules::RuleOverrides;
+use crate::schedule::OpeningSchedule;
+use crate::storage::Backend;
+
+/// A directory in the temporary directory, named after the test, removed when dropped.
+pub struct TempDir {
+    /// Where the directory is.
+    pub path: PathBuf,
+}
+
+impl TempDir {
+    /// Create the directory, empty even if a previous run left it behind.
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("first_top-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        Self { path }
+    }
+
+    /// Path of a file in the directory.
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+
+    /// Contents of a file of the directory.
+    pub fn read(&self, name: &str) -> String {
+        fs::read_to_string(self.join(name)).unwrap()
+    }
+
+    /// Names of the files in the directory, sorted.
+    pub fn files(&self) -> Vec<String> {
+        let mut files: Vec<String> = fs::read_dir(&self.path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+
+        files.sort();
+        files
+    }
+
+    /// A config with its collections in the directory.
+    pub fn config(&self) -> ConfigBuilder {
+        ConfigBuilder::new(&self.path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // Tests of read-only directories may leave it so.
+        let _ = fs::set_permissions(&self.path, fs::Permissions::from_mode(0o755));
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A config of the CSV collections first_results and first_openings, with the default rules.
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    /// Start from the config of the collections in data_dir.
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            config: Config {
+                data_dir: data_dir.to_path_buf(),
+                collection: String::from("first_results"),
+                extension: String::from("csv"),
+                openings: String::from("first_openings"),
+                backend: Backend::Csv,
+                backups: 0,
+                headers: false,
+                lock_timeout: Duration::from_secs(1),
+                rules: RuleOverrides::default(),
+                channels: HashMap::new(),
+            },
+        }
+    }
+
+    /// Keep that many backups when rewriting the collections.
+    pub fn with_backups(mut self, backups: usize) -> Self {
+        self.config.backups = backups;
+        self
+    }
+
+    /// Use the rules shared by all channels.
+    pub fn with_rules(mut self, rules: RuleOverrides) -> Self {
+        self.config.rules = rules;
+        self
+    }
+
+    /// Open at the times of the schedule, in the syntax of the config file.
+    pub fn with_schedule(mut self, schedule: &str) -> Self {
+        self.config.rules.schedule = Some(OpeningSchedule::parse(schedule).unwrap());
+        self
+    }
+
+    /// The config built.
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
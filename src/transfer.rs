@@ -0,0 +1,262 @@
+//! Moving results in and out of a collection: importing and exporting CSV files,
+//! and migrating the collection to the current layout or to another backend.
+
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use crate::FirstResult;
+use crate::config::Config;
+use crate::database::{CsvRecord, Database, read_file, write_file};
+use crate::error::{Error, Result};
+#[cfg(feature = "sqlite")]
+use crate::openings::Opening;
+use crate::query::Query;
+use crate::reports::{first_results, warned};
+use crate::storage::{Backend, Storage};
+
+/// Add the results of a CSV file to the collection, skipping those it already has,
+/// and return the number of results added and of duplicates skipped.
+///
+/// The file is read as it is, without a lock, and must have the `.csv` extension of the only format supported.
+/// The collection is rewritten whole in chronological order, failing rather than losing its invalid rows.
+/// The errors of the rows of the file skipped (unless strict) are added to warnings.
+pub fn import(
+    db: &impl Storage,
+    config: &Config,
+    file: &Path,
+    strict: bool,
+    warnings: &mut Vec<Error>,
+) -> Result<(usize, usize)> {
+    check_format(file)?;
+
+    let imported: Vec<FirstResult> = warned(read_file(file, |_| true, strict), warnings)?
+        .ok_or_else(|| Error::NoData(format!("No results found in {}", file.display())))?;
+    let total = imported.len();
+    let mut added = 0;
+
+    db.rewrite(&config.collection, |mut first_results: Vec<FirstResult>| {
+        let mut known: HashSet<Vec<String>> =
+            first_results.iter().map(CsvRecord::to_fields).collect();
+
+        for r in imported {
+            if known.insert(r.to_fields()) {
+                first_results.push(r);
+                added += 1;
+            }
+        }
+
+        // Keep the collection in chronological order, as ranking groups consecutive entries by day.
+        first_results.sort_by_key(|r| r.datetime);
+
+        Ok(first_results)
+    })?;
+
+    Ok((added, total - added))
+}
+
+/// Select the results of the query to export, writing them to a CSV file if one is given.
+///
+/// The file is replaced without a lock, and must have the `.csv` extension of the only format supported.
+/// The errors of the rows skipped (unless the query is strict) are added to warnings.
+pub fn export(
+    db: &impl Storage,
+    config: &Config,
+    query: &Query,
+    output: Option<&Path>,
+    warnings: &mut Vec<Error>,
+) -> Result<Vec<FirstResult>> {
+    if let Some(file) = output {
+        check_format(file)?;
+    }
+
+    let first_results = first_results(db, config, query, false, warnings)?;
+
+    if let Some(file) = output {
+        write_file(file, &first_results.iter().collect::<Vec<_>>())?;
+    }
+
+    Ok(first_results)
+}
+
+/// Rewrite the collection in the current layout of the configured backend, returning the number of results.
+pub fn migrate(db: &impl Storage, config: &Config) -> Result<usize> {
+    let mut count = 0;
+
+    db.rewrite(&config.collection, |first_results: Vec<FirstResult>| {
+        count = first_results.len();
+
+        Ok(first_results)
+    })?;
+
+    Ok(count)
+}
+
+/// Copy the results and recorded opening times from the configured backend into another one,
+/// replacing them there, and return the number of results and of opening times copied.
+#[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+pub fn migrate_into(db: &Database, config: &Config, into: Backend) -> Result<(usize, usize)> {
+    into.available()?;
+
+    match (config.backend, into) {
+        (Backend::Csv, Backend::Csv) | (Backend::Sqlite, Backend::Sqlite) => {
+            Err(Error::Query(format!(
+                "The collection is already stored in the {} backend",
+                into.name()
+            )))
+        }
+        #[cfg(feature = "sqlite")]
+        (Backend::Csv, Backend::Sqlite) => copy(db, &config.sqlite(), config),
+        #[cfg(feature = "sqlite")]
+        (Backend::Sqlite, Backend::Csv) => copy(&config.sqlite(), db, config),
+        #[cfg(not(feature = "sqlite"))]
+        _ => unreachable!("the sqlite backend is rejected by Backend::available"),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn copy(from: &impl Storage, to: &impl Storage, config: &Config) -> Result<(usize, usize)> {
+    // Strictly, so no invalid row is left behind.
+    let first_results: Vec<FirstResult> = from
+        .select(&config.collection, |_| true)?
+        .ok_or_else(|| Error::NoData(String::from("No results found")))?;
+
+    let openings: Vec<Opening> = from.select(&config.openings, |_| true)?.unwrap_or_default();
+
+    to.write(
+        &config.collection,
+        &first_results.iter().collect::<Vec<_>>(),
+    )?;
+
+    if !openings.is_empty() {
+        to.write(&config.openings, &openings.iter().collect::<Vec<_>>())?;
+    }
+
+    Ok((first_results.len(), openings.len()))
+}
+
+/// Check that a file to import or export is in a supported format, as its extension tells.
+fn check_format(file: &Path) -> Result<()> {
+    match file.extension().map(OsStr::to_string_lossy) {
+        Some(extension) if extension.eq_ignore_ascii_case("csv") => Ok(()),
+        Some(extension) => Err(Error::Query(format!(
+            "Unknown format {extension} of {}, only CSV files are supported",
+            file.display()
+        ))),
+        None => Err(Error::Query(format!(
+            "Missing format of {}, which needs the .csv extension",
+            file.display()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io};
+
+    use super::*;
+    use crate::test_support::TempDir;
+
+    const ROWS: &str = "alice,#test,2025-03-14 08:00:00.300 UTC,UTC\n\
+                        bob,#test,2025-03-15 08:00:00.200 UTC,UTC\n";
+
+    #[test]
+    fn imports_read_the_file_without_a_lock_or_an_index() {
+        let dir = TempDir::new("transfer-import");
+        let files = TempDir::new("transfer-import-files");
+        let config = dir.config().build();
+        let file = files.join("new.csv");
+        fs::write(
+            config.data_dir.join("first_results.csv"),
+            "bob,#test,2025-03-15 08:00:00.200 UTC,UTC\n",
+        )
+        .unwrap();
+        fs::write(&file, format!("{ROWS}carol,#test,yesterday,UTC\n")).unwrap();
+
+        let mut warnings = Vec::new();
+        let db = config.database().unwrap();
+        let imported = import(&db, &config, &file, false, &mut warnings).unwrap();
+
+        assert_eq!(imported, (1, 1));
+        assert!(matches!(warnings[..], [Error::Record { line: 3, .. }]));
+        assert_eq!(files.files(), ["new.csv"]);
+        assert_eq!(
+            fs::read_to_string(config.data_dir.join("first_results.csv")).unwrap(),
+            ROWS
+        );
+        assert!(matches!(
+            import(&db, &config, &file, true, &mut warnings),
+            Err(Error::Record { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn importing_a_missing_file_fails() {
+        let dir = TempDir::new("transfer-missing");
+        let files = TempDir::new("transfer-missing-files");
+        let config = dir.config().build();
+        let db = config.database().unwrap();
+
+        assert!(matches!(
+            import(&db, &config, &files.join("missing.csv"), false, &mut Vec::new()),
+            Err(Error::Io { source, .. }) if source.kind() == io::ErrorKind::NotFound
+        ));
+        assert!(files.files().is_empty());
+    }
+
+    #[test]
+    fn exports_write_the_file_without_a_lock() {
+        let dir = TempDir::new("transfer-export");
+        let files = TempDir::new("transfer-export-files");
+        let config = dir.config().build();
+        let file = files.join("out.CSV");
+        fs::write(config.data_dir.join("first_results.csv"), ROWS).unwrap();
+        fs::write(&file, "replaced\n").unwrap();
+
+        let db = config.database().unwrap();
+        let exported = export(
+            &db,
+            &config,
+            &Query::default(),
+            Some(&file),
+            &mut Vec::new(),
+        );
+
+        assert_eq!(exported.unwrap().len(), 2);
+        assert_eq!(fs::read_to_string(&file).unwrap(), ROWS);
+        assert_eq!(files.files(), ["out.CSV"]);
+    }
+
+    #[test]
+    fn files_of_unknown_or_missing_formats_are_rejected() {
+        let dir = TempDir::new("transfer-formats");
+        let files = TempDir::new("transfer-formats-files");
+        let config = dir.config().build();
+        fs::write(config.data_dir.join("first_results.csv"), ROWS).unwrap();
+
+        let db = config.database().unwrap();
+
+        for name in ["results", "results.txt", "results.csv.gz"] {
+            let file = files.join(name);
+            fs::write(&file, ROWS).unwrap();
+
+            assert!(matches!(
+                import(&db, &config, &file, false, &mut Vec::new()),
+                Err(Error::Query(_))
+            ));
+            fs::remove_file(&file).unwrap();
+
+            assert!(matches!(
+                export(
+                    &db,
+                    &config,
+                    &Query::default(),
+                    Some(&file),
+                    &mut Vec::new()
+                ),
+                Err(Error::Query(_))
+            ));
+            assert!(files.files().is_empty());
+        }
+    }
+}